@@ -1,9 +1,21 @@
-#[cfg_attr(not(any(feature = "jpeg-opt", feature = "png-opt")), expect(unused_imports))]
-use clap::{Arg, ArgAction, command};
-use rusty_cov::run;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, Command, command};
+use rusty_cov::backup::undo;
+use rusty_cov::cancel::CancellationToken;
+use rusty_cov::cover_art_archive::{CoverArtArchiveSource, CoverArtSize};
+use rusty_cov::lofty::{SkipExisting, StripPolicy, TagTarget};
+use rusty_cov::name_pattern::{BUILTIN_PATTERNS, NamePattern};
+use rusty_cov::observer::TerminalObserver;
+use rusty_cov::options::RunOptions;
+use rusty_cov::query::{Country, QueryOverride, QuerySource};
+use rusty_cov::rank::RankingRules;
+use rusty_cov::structs::CoverUrlKind;
+use rusty_cov::{plan, run_with, scan};
 
 fn main() {
-    #[cfg_attr(not(any(feature = "jpeg-opt", feature = "png-opt")), expect(unused_mut))]
     let mut cmd = command!()
         .arg(
             Arg::new("input_string")
@@ -23,6 +35,14 @@ fn main() {
                 .help("Address of the COV website to open on launch.")
                 .long_help("Enter the URL of the COV website that you want to be opened when the application launches."),
             )
+        .arg(
+            Arg::new("covit")
+                .long("covit")
+                .num_args(1)
+                .value_name("PATH")
+                .help("covit binary to use instead of downloading it")
+                .long_help("Run the covit binary at PATH instead of looking for covit in deps_bin and on the PATH, e.g. a fake covit for offline testing."),
+        )
         .arg(
             Arg::new("album_mode")
                 .short('a')
@@ -31,7 +51,266 @@ fn main() {
                 .value_name("COVER_NAME")
                 .help("Process in album folder mode")
                 .long_help("Write the selected image into the directory with the associated song and remove embedded images from other music files in the directory, resulting in each folder having a single album cover image."),
-            );
+            )
+        .arg(
+            Arg::new("extract")
+                .long("extract")
+                .num_args(1)
+                .value_name("COVER_NAME")
+                .conflicts_with("album_mode")
+                .help("Write the embedded front cover of each folder to COVER_NAME")
+                .long_help("Write the best front cover already embedded in the songs of each directory (largest, then most common) as COVER_NAME with the extension of its image type. Nothing is downloaded and covit is not run; directories that already have the cover file are skipped."),
+        )
+        .arg(
+            Arg::new("strip_extracted")
+                .long("strip-extracted")
+                .requires("extract")
+                .action(ArgAction::SetTrue)
+                .help("Remove the embedded art once it is extracted")
+                .long_help("Remove the embedded pictures selected by --strip from the songs of a directory once its cover is extracted."),
+        )
+        .arg(
+            Arg::new("query_sources")
+                .long("query-sources")
+                .num_args(1)
+                .value_name("SOURCES")
+                .value_parser(QuerySource::parse_list)
+                .help("Comma separated stores covit searches, e.g. musicbrainz,discogs")
+                .long_help(format!(
+                    "Comma separated stores covit searches. Known sources: {}. Defaults to {}.",
+                    QuerySource::ALL.map(|s| s.as_str()).join(", "),
+                    QuerySource::DEFAULT.map(|s| s.as_str()).join(",")
+                )),
+        )
+        .arg(
+            Arg::new("query_country")
+                .long("query-country")
+                .num_args(1)
+                .value_name("COUNTRY")
+                .value_parser(clap::value_parser!(Country))
+                .help("Two-letter country code of the storefronts covit searches")
+                .long_help(format!("Two-letter country code of the storefronts covit searches, e.g. jp or us. Defaults to {}.", Country::DEFAULT)),
+        )
+        .arg(
+            Arg::new("query_override")
+                .long("query-override")
+                .num_args(1)
+                .value_name("DIR=SOURCES@COUNTRY")
+                .value_parser(clap::value_parser!(QueryOverride))
+                .action(ArgAction::Append)
+                .help("Use other sources and/or country for files in and below DIR")
+                .long_help("Use other sources and/or country for every file in and below DIR, given as DIR=SOURCES, DIR=@COUNTRY or DIR=SOURCES@COUNTRY, e.g. Classical=musicbrainz,discogs@us. A relative DIR is taken from the input directory, or from the current directory if it does not exist there. May be repeated; the deepest matching directory wins."),
+        )
+        .arg(
+            Arg::new("name_pattern")
+                .long("name-pattern")
+                .num_args(1)
+                .value_name("PATTERN")
+                .value_parser(clap::value_parser!(NamePattern))
+                .action(ArgAction::Append)
+                .help("Pattern extracting artist and album from file and folder names, e.g. \"{track} - {artist} - {album}\"")
+                .long_help(format!(
+                    "Pattern extracting the covit query from file and folder names when the tags are not enough. Fields are {{artist}}, {{albumartist}}, {{album}}, {{title}}, {{track}}, {{disc}}, {{year}} and {{_}}; segments separated by / match the parent folders and a last segment of … matches any file name, e.g. \"{{artist}}/{{year}} - {{album}}/…\". May be repeated; patterns are tried in order before the built-in ones: {}.",
+                    BUILTIN_PATTERNS.join(", ")
+                )),
+        )
+        .arg(
+            Arg::new("ask_per_file")
+                .long("ask-per-file")
+                .help("Ask for a cover for every file, e.g. for compilations")
+                .long_help("In per-file mode, once a cover is picked for an album (same album artist and album tags, or the same folder when they are missing), the other tracks reuse it without opening covit again. This flag asks for every file instead, e.g. for compilations whose tracks need different covers.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("cover_source")
+                .long("cover-source")
+                .num_args(1)
+                .value_name("SOURCE")
+                .value_parser(["covit", "cover-art-archive"])
+                .default_value("covit")
+                .help("Where to look for covers: covit or cover-art-archive")
+                .long_help("covit asks you to pick a cover in the browser. cover-art-archive looks up the front cover of the MusicBrainz release tagged in each file (MUSICBRAINZ_ALBUMID / MusicBrainz Album Id) without any search; files without the tag get no cover."),
+        )
+        .arg(
+            Arg::new("cover_art_archive_url")
+                .long("cover-art-archive-url")
+                .num_args(1)
+                .value_name("URL")
+                .requires("cover_source")
+                .help("Cover Art Archive server, default https://coverartarchive.org"),
+        )
+        .arg(
+            Arg::new("musicbrainz_url")
+                .long("musicbrainz-url")
+                .num_args(1)
+                .value_name("URL")
+                .requires("cover_source")
+                .help("MusicBrainz server queried for release info, default https://musicbrainz.org"),
+        )
+        .arg(
+            Arg::new("cover_art_size")
+                .long("cover-art-size")
+                .num_args(1)
+                .value_name("SIZE")
+                .value_parser(clap::value_parser!(CoverArtSize))
+                .requires("cover_source")
+                .help("Cover Art Archive image used as the cover: 1200, 500 or original, default 1200"),
+        )
+        .arg(
+            Arg::new("auto_pick")
+                .long("auto-pick")
+                .help("Pick covers without asking, deferring low-confidence items")
+                .long_help("Score the candidates of the cover source on album and artist similarity to the tags, resolution, squareness, --prefer-source and release year, and use the best one if it scores at least --auto-pick-threshold. Folders and files below it are left untouched for a later interactive run. Requires --cover-source cover-art-archive, since covit asks you to pick.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("auto_pick_threshold")
+                .long("auto-pick-threshold")
+                .num_args(1)
+                .value_name("SCORE")
+                .value_parser(|score: &str| match score.parse::<f64>() {
+                    Ok(score) if (0.0..=1.0).contains(&score) => Ok(score),
+                    _ => Err(format!("{} is not a score between 0 and 1", score)),
+                })
+                .requires("auto_pick")
+                .help("Lowest score accepted by --auto-pick, from 0 to 1, default 0.7"),
+        )
+        .arg(
+            Arg::new("prefer_source")
+                .long("prefer-source")
+                .num_args(1)
+                .value_name("SOURCES")
+                .value_delimiter(',')
+                .requires("auto_pick")
+                .help("Comma separated sources favoured by --auto-pick, most preferred first"),
+        )
+        .arg(
+            Arg::new("cover_url_order")
+                .long("cover-url-order")
+                .num_args(1)
+                .value_name("URLS")
+                .value_parser(|urls: &str| urls.split(',').map(str::parse).collect::<Result<Vec<CoverUrlKind>, String>>())
+                .help("Cover URLs of a pick to try in order, default big,original,small")
+                .long_help("Comma separated cover URLs of a pick tried in order until one downloads and meets --min-cover-size: big (the big cover from COV), original (the image on the store itself) and small. Defaults to big,original,small."),
+        )
+        .arg(
+            Arg::new("min_cover_size")
+                .long("min-cover-size")
+                .num_args(1)
+                .value_name("PIXELS")
+                .value_parser(clap::value_parser!(u32))
+                .help("Smallest acceptable cover width and height in pixels")
+                .long_help("Reject downloaded covers narrower or shorter than PIXELS and try the next cover URL instead."),
+        )
+        .arg(
+            Arg::new("embed_tags")
+                .long("embed-tags")
+                .num_args(1)
+                .value_name("TAGS")
+                .value_parser(clap::value_parser!(TagTarget))
+                .help("Tags to embed the cover into: primary, all or a list such as id3v2,ape")
+                .long_help("Tags of each file the cover is embedded into in per-file mode: primary (the default) writes the main tag only, all writes every existing tag that can hold pictures, and a comma separated list of ape, id3v2, mp4ilst and vorbis writes those tags, creating them if the format supports them."),
+        )
+        .arg(
+            Arg::new("skip_existing")
+                .long("skip-existing")
+                .num_args(1)
+                .value_name("POLICY")
+                .value_parser(clap::value_parser!(SkipExisting))
+                .conflicts_with_all(["album_mode", "extract"])
+                .help("Skip files that already embed a good enough front cover: any, WIDTHxHEIGHT or since:MARKER")
+                .long_help("Leave files alone in per-file mode, without launching covit, when their embedded front cover is good enough: any accepts every front cover, a size such as 1400 or 1400x1000 requires at least that width and height in pixels, and since:MARKER requires a cover embedded by RustyCOV no earlier than MARKER, given as the marker ending the picture description of a file (RustyCOV <timestamp>), a Unix timestamp or a YYYY-MM-DD date. Every cover RustyCOV embeds gets this marker appended to its picture description, with or without --skip-existing, replacing an older marker and keeping the rest of the description. Skipped files are counted separately and listed by --dry-run."),
+        )
+        .arg(
+            Arg::new("strip")
+                .long("strip")
+                .num_args(1)
+                .value_name("POLICY")
+                .value_parser(clap::value_parser!(StripPolicy))
+                .help("Pictures to remove in album folder mode: front, keep-booklet or all")
+                .long_help("Embedded pictures removed from every tag of the files once the album cover is saved in album folder mode, or extracted with --strip-extracted: front (the default) removes front covers only, keep-booklet removes everything except booklet pages and back covers, and all removes every picture."),
+        )
+        .arg(
+            Arg::new("pick_timeout")
+                .long("pick-timeout")
+                .num_args(1)
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Give up on a file when no cover is picked within SECS seconds")
+                .long_help("Stop covit and move on to the next file when no cover is picked within SECS seconds. Waits forever by default."),
+        )
+        .arg(
+            Arg::new("jobs")
+                .long("jobs")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Number of covers to download and embed in parallel")
+                .long_help("Number of workers downloading, processing and embedding covers while you keep picking. Defaults to the number of CPU cores."),
+        )
+        .arg(
+            Arg::new("journal")
+                .long("journal")
+                .num_args(1)
+                .value_name("JOURNAL")
+                .conflicts_with("resume")
+                .help("Record progress in a journal file so the run can be resumed")
+                .long_help("Record finished folders, files and every cover picked in covit to the given file. Pass it to --resume after a crash or a closed terminal to carry on where the run stopped."),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .num_args(1)
+                .value_name("JOURNAL")
+                .help("Resume the run recorded in a journal file")
+                .long_help("Skip the folders and files the journal records as finished and redo unfinished downloads and embeds using the recorded picks, without asking covit again. New progress is appended to the same journal."),
+        )
+        .arg(
+            Arg::new("backup")
+                .long("backup")
+                .num_args(1)
+                .value_name("BACKUP_DIR")
+                .help("Back up original pictures before modifying any file")
+                .long_help("Save the pictures of every file before it is embedded into or stripped, along with the list of cover files written, to a new session directory below BACKUP_DIR. Revert the session with the `undo` command."),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .num_args(1)
+                .value_name("REPORT_FILE")
+                .help("Write a JSON report of every folder and file to REPORT_FILE")
+                .long_help("Write the outcome of every folder and file (embedded, stripped, skipped, error...), the picked cover and the image sizes before and after processing to REPORT_FILE as JSON once the run ends."),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .help("Show every change without touching any file")
+                .long_help("Print which folders would get a cover and which files would be embedded, stripped or skipped, along with the picture bytes each file carries. covit is not launched and nothing is written.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("scan")
+                .long("scan")
+                .conflicts_with("dry_run")
+                .help("Report the artwork status of every folder without touching any file")
+                .long_help("Print, for every directory, the cover file and its dimensions, the pictures embedded in each file with their types, MIME types, dimensions and bytes, and whether the art is missing, low resolution (below --min-cover-size, 500 pixels by default), inconsistent across tracks or both in a cover file and embedded. covit is not launched and nothing is written.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .num_args(1)
+                .value_name("FORMAT")
+                .value_parser(["table", "json", "csv"])
+                .default_value("table")
+                .help("Output format for --dry-run and --scan (csv is only supported by --scan)"),
+        )
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("undo")
+                .about("Restore the pictures saved by a --backup session and delete the cover files it wrote")
+                .arg(Arg::new("session").required(true).value_name("SESSION_DIR").help("Session directory printed when the backup started")),
+        );
 
     // Conditionally add arguments
     #[cfg(feature = "jpeg-opt")]
@@ -50,9 +329,9 @@ fn main() {
                 Arg::new("jpeg_optimise")
                     .short('j')
                     .long("jpeg-optimise")
-                    .help("Optimise JPEG images with specified quality (0-100, recommended: 80)")
+                    .help("Optimise JPEG images with specified quality (1-100, recommended: 80)")
                     .value_name("JPEG_QUALITY_NUMBER")
-                    .value_parser(value_parser!(u8)),
+                    .value_parser(value_parser!(u8).range(1..=100)),
             )
     }
 
@@ -68,24 +347,172 @@ fn main() {
         );
     }
 
-    let matches = cmd.get_matches();
+    let matches = cmd.get_matches_mut();
+
+    if let Some(undo_matches) = matches.subcommand_matches("undo") {
+        let session = undo_matches.get_one::<String>("session").expect("session is required");
+        match undo(session.as_ref(), &TerminalObserver::new()) {
+            Ok(summary) => println!("Restored {} file(s), removed {} cover file(s).", summary.files_restored, summary.sidecars_removed),
+            Err(e) => eprintln!("Failed to undo session: {}", e),
+        }
+        return;
+    }
+
+    // The first Ctrl-C lets the files being written finish and prints the partial totals, a
+    // second one exits immediately.
+    let cancellation = CancellationToken::new();
+    let handler_token = cancellation.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_token.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("\nCancelling, finishing files and folders being written (press Ctrl-C again to exit now)...");
+        handler_token.cancel();
+    }) {
+        eprintln!("Failed to set Ctrl-C handler: {}", e);
+    }
+
+    let mut options = RunOptions::builder()
+        .cancellation_token(cancellation)
+        .dry_run(matches.get_flag("dry_run"))
+        .scan(matches.get_flag("scan"))
+        .ask_per_file(matches.get_flag("ask_per_file"))
+        .observer(Arc::new(TerminalObserver::new()))
+        .convert_png_to_jpg(matches.get_flag("png_to_jpeg"))
+        .jpeg_optimise(matches.get_one::<u8>("jpeg_optimise").copied())
+        .png_opt(matches.get_flag("png_optimise"));
+    if let Some(input) = matches.get_one::<String>("input_string") {
+        options = options.input(input);
+    }
+    if let Some(cov_address) = matches.get_one::<String>("cov_url") {
+        options = options.cov_address(cov_address);
+    }
+    if let Some(covit) = matches.get_one::<String>("covit") {
+        options = options.covit_path(covit);
+    }
+    if let Some(cover_image_name) = matches.get_one::<String>("album_mode") {
+        options = options.album_folder_mode(cover_image_name);
+    }
+    if let Some(cover_image_name) = matches.get_one::<String>("extract") {
+        options = options.extract(cover_image_name).strip_extracted(matches.get_flag("strip_extracted"));
+    }
+    if let Some(sources) = matches.get_one::<Vec<QuerySource>>("query_sources") {
+        options = options.query_sources(sources.clone());
+    }
+    if let Some(country) = matches.get_one::<Country>("query_country") {
+        options = options.query_country(country.clone());
+    }
+    for query_override in matches.get_many::<QueryOverride>("query_override").into_iter().flatten() {
+        options = options.query_override(query_override.clone());
+    }
+    for pattern in matches.get_many::<NamePattern>("name_pattern").into_iter().flatten() {
+        options = options.name_pattern(pattern.clone());
+    }
+    if matches.get_one::<String>("cover_source").is_some_and(|source| source == "cover-art-archive") {
+        let mut source = CoverArtArchiveSource::default();
+        if let Some(url) = matches.get_one::<String>("cover_art_archive_url") {
+            source = CoverArtArchiveSource::new(url);
+        }
+        if let Some(url) = matches.get_one::<String>("musicbrainz_url") {
+            source = source.musicbrainz_url(Some(url.clone()));
+        }
+        if let Some(size) = matches.get_one::<CoverArtSize>("cover_art_size") {
+            source = source.size(*size);
+        }
+        options = options.cover_source(Arc::new(source));
+    } else if ["cover_art_archive_url", "musicbrainz_url", "cover_art_size"].into_iter().any(|id| matches.contains_id(id)) {
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--cover-art-archive-url, --musicbrainz-url and --cover-art-size require --cover-source cover-art-archive",
+        )
+        .exit();
+    }
+    if matches.get_flag("auto_pick") {
+        if matches.get_one::<String>("cover_source").is_some_and(|source| source == "covit") {
+            cmd.error(ErrorKind::ArgumentConflict, "--auto-pick requires --cover-source cover-art-archive, covit asks you to pick").exit();
+        }
+        let mut rules = RankingRules::default();
+        if let Some(threshold) = matches.get_one::<f64>("auto_pick_threshold") {
+            rules.threshold = *threshold;
+        }
+        rules.preferred_sources = matches.get_many::<String>("prefer_source").into_iter().flatten().cloned().collect();
+        options = options.auto_pick(rules);
+    }
+    if let Some(order) = matches.get_one::<Vec<CoverUrlKind>>("cover_url_order") {
+        options = options.cover_url_order(order.clone());
+    }
+    options = options.min_cover_size(matches.get_one::<u32>("min_cover_size").copied());
+    if let Some(tag_target) = matches.get_one::<TagTarget>("embed_tags") {
+        options = options.tag_target(tag_target.clone());
+    }
+    if let Some(policy) = matches.get_one::<SkipExisting>("skip_existing") {
+        options = options.skip_existing(*policy);
+    }
+    if let Some(strip_policy) = matches.get_one::<StripPolicy>("strip") {
+        options = options.strip_policy(*strip_policy);
+    }
+    options = options.pick_timeout(matches.get_one::<u64>("pick_timeout").map(|secs| Duration::from_secs(*secs)));
+    if let Some(jobs) = matches.get_one::<usize>("jobs") {
+        options = options.jobs(*jobs);
+    }
+    if let Some(journal) = matches.get_one::<String>("journal") {
+        options = options.journal(journal);
+    }
+    if let Some(journal) = matches.get_one::<String>("resume") {
+        options = options.resume(journal);
+    }
+    if let Some(backup_dir) = matches.get_one::<String>("backup") {
+        options = options.backup_dir(backup_dir);
+    }
+
+    let options = options.build();
 
-    let input = match matches.get_one::<String>("input_string") {
-        Some(s) => s.as_str(),
-        None => ".",
-    };
-    let cov_address = matches.get_one::<String>("cov_url").map(|s| s.as_str());
-    let cover_image_name = matches.get_one::<String>("album_mode").map(|s| s.as_str());
+    if options.dry_run {
+        let plan = match plan(&options) {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("Failed to plan the run: {}", e);
+                return;
+            }
+        };
+        match matches.get_one::<String>("format").map(|s| s.as_str()) {
+            Some("json") => match serde_json::to_string_pretty(&plan) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Failed to serialise plan: {}", e),
+            },
+            _ => println!("{}", plan),
+        }
+        return;
+    }
+
+    if options.scan {
+        let scan = match scan(&options) {
+            Ok(scan) => scan,
+            Err(e) => {
+                eprintln!("Failed to scan: {}", e);
+                return;
+            }
+        };
+        match matches.get_one::<String>("format").map(|s| s.as_str()) {
+            Some("json") => match serde_json::to_string_pretty(&scan) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Failed to serialise scan: {}", e),
+            },
+            Some("csv") => print!("{}", scan.to_csv()),
+            _ => println!("{}", scan),
+        }
+        return;
+    }
 
-    match run(
-        input,
-        cov_address,
-        matches.get_flag("png_to_jpeg"),
-        matches.get_one::<u8>("jpeg_optimise").copied(),
-        matches.get_flag("png_optimise"),
-        cover_image_name,
-    ) {
-        Ok(_) => {}
+    match run_with(&options) {
+        Ok(report) => {
+            if let Some(report_path) = matches.get_one::<String>("report") {
+                let written = serde_json::to_string_pretty(&report).map_err(std::io::Error::from).and_then(|json| std::fs::write(report_path, json));
+                if let Err(e) = written {
+                    eprintln!("Failed to write report to {:?}: {}", report_path, e);
+                }
+            }
+        }
         Err(e) => eprintln!("Failed to run application: {}", e),
     }
 }
@@ -0,0 +1,19 @@
+//! Offline stand-in for covit.
+//!
+//! The `fake_covit` binary accepts covit's command line but, instead of opening a browser, asks
+//! the [`FakeCov`](server::FakeCov) server passed as `--address` what to print. A test starts its
+//! own server, scripts the answers with [`FakeCov::respond`](server::FakeCov::respond), serves the
+//! cover images from it and points `rusty_cov` at both:
+//!
+//! ```no_run
+//! use fake_covit::protocol::Response;
+//! use fake_covit::server::{FakeCov, Match};
+//!
+//! let cov = FakeCov::start().unwrap();
+//! let url = cov.image("cover.png", std::fs::read("cover.png").unwrap());
+//! cov.respond(Match::Any, Response::pick(&url));
+//! // RunOptions::builder().covit_path(env!("CARGO_BIN_EXE_fake_covit")).cov_address(cov.address())
+//! ```
+
+pub mod protocol;
+pub mod server;
@@ -0,0 +1,41 @@
+use std::io::Write;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use fake_covit::protocol::{Invocation, Response, ask};
+
+fn main() -> ExitCode {
+    let invocation = match Invocation::from_args(std::env::args().skip(1)) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+
+    println!("Listening: {}", invocation.address);
+    let _ = std::io::stdout().flush();
+
+    let response = match ask(&invocation) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Failed to reach {}: {}", invocation.address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match response {
+        Response::Pick { picked } => println!("Picked: {}", picked),
+        Response::Close => {}
+        Response::NotFound => println!("No covers found"),
+        Response::Crash { status, stderr } => {
+            eprintln!("{}", stderr);
+            return ExitCode::from(status);
+        }
+        Response::Hang => loop {
+            thread::sleep(Duration::from_secs(60));
+        },
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// Command line a fake covit was started with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invocation {
+    /// `--address`, the [`FakeCov`](crate::server::FakeCov) server to ask.
+    pub address: String,
+    /// `--input`, the audio file covit was run on.
+    pub input: Option<PathBuf>,
+    /// `--query-album`.
+    pub query_album: Option<String>,
+    /// `--query-artist`.
+    pub query_artist: Option<String>,
+    /// `--remote-agent`.
+    pub remote_agent: Option<String>,
+    /// `--query-sources`, comma separated.
+    pub query_sources: Option<String>,
+    /// `--query-country`.
+    pub query_country: Option<String>,
+}
+
+impl Invocation {
+    /// Parses covit's command line, without the program name.
+    ///
+    /// Returns the message covit would print for an unknown flag, a flag without a value or a
+    /// missing `--address`.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut invocation = Invocation::default();
+        let mut address = None;
+        let mut args = args.into_iter();
+
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+            match flag.as_str() {
+                "--address" => address = Some(value),
+                "--input" => invocation.input = Some(PathBuf::from(value)),
+                "--query-album" => invocation.query_album = Some(value),
+                "--query-artist" => invocation.query_artist = Some(value),
+                "--remote-agent" => invocation.remote_agent = Some(value),
+                "--query-sources" => invocation.query_sources = Some(value),
+                "--query-country" => invocation.query_country = Some(value),
+                _ => return Err(format!("Unknown flag {}", flag)),
+            }
+        }
+
+        invocation.address = address.ok_or("Missing --address")?;
+        Ok(invocation)
+    }
+}
+
+/// What a fake covit does once started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Response {
+    /// Print `Picked: <picked>` and exit.
+    Pick { picked: Value },
+    /// Exit after `Listening:` only, as when the user closes the tab.
+    Close,
+    /// Print that nothing was found and exit successfully.
+    NotFound,
+    /// Print `stderr` and exit with `status`.
+    Crash { status: u8, stderr: String },
+    /// Wait until killed, as when the user never picks a cover.
+    Hang,
+}
+
+impl Response {
+    /// Picks the cover at `big_cover_url`.
+    pub fn pick(big_cover_url: &str) -> Self {
+        Response::Pick { picked: json!({ "source": "fake", "bigCoverUrl": big_cover_url }) }
+    }
+}
+
+/// Sends `invocation` to the server at its address and returns the scripted response.
+pub fn ask(invocation: &Invocation) -> io::Result<Response> {
+    let host = invocation.address.trim_start_matches("http://").trim_end_matches('/');
+    let body = serde_json::to_vec(invocation)?;
+
+    let mut stream = TcpStream::connect(host)?;
+    write!(stream, "POST /covit HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", host, body.len())?;
+    stream.write_all(&body)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let body_start = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed HTTP response"))?;
+    Ok(serde_json::from_slice(&response[body_start + 4..])?)
+}
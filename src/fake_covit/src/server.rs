@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::protocol::{Invocation, Response};
+
+/// Selects the fake covit invocations a scripted [`Response`] applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Match {
+    /// Every invocation.
+    Any,
+    /// Invocations whose `--input` file name contains the string.
+    Input(String),
+    /// Invocations whose `--query-album` equals the string.
+    Album(String),
+}
+
+impl Match {
+    pub fn matches(&self, invocation: &Invocation) -> bool {
+        match self {
+            Match::Any => true,
+            Match::Input(name) => invocation
+                .input
+                .as_ref()
+                .and_then(|input| input.file_name())
+                .is_some_and(|file_name| file_name.to_string_lossy().contains(name.as_str())),
+            Match::Album(album) => invocation.query_album.as_deref() == Some(album.as_str()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    rules: Vec<(Match, Response)>,
+    /// Documents served by path, without the query string.
+    documents: HashMap<String, (&'static str, Vec<u8>)>,
+    invocations: Vec<Invocation>,
+}
+
+/// Local HTTP server scripting fake covit runs and serving their cover images, along with any
+/// JSON document a mocked web service should return.
+///
+/// The server runs on a background thread for the rest of the process. Invocations matching no
+/// rule get [`Response::NotFound`].
+#[derive(Clone)]
+pub struct FakeCov {
+    address: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeCov {
+    /// Starts a server on a free port of the loopback interface.
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&server_state);
+                thread::spawn(move || {
+                    let _ = handle(stream, &state);
+                });
+            }
+        });
+
+        Ok(Self { address, state })
+    }
+
+    /// Returns the address to pass to covit, e.g. as the COV address of a run.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Serves `bytes` under `name` and returns its URL.
+    pub fn image(&self, name: &str, bytes: Vec<u8>) -> String {
+        let path = format!("/images/{}", name);
+        self.state.lock().unwrap().documents.insert(path.clone(), ("application/octet-stream", bytes));
+        format!("{}{}", self.address, path)
+    }
+
+    /// Serves `document` at `path`, e.g. `/release/<id>`, whatever the query string, and returns
+    /// its URL.
+    pub fn json(&self, path: &str, document: &serde_json::Value) -> String {
+        let bytes = serde_json::to_vec(document).expect("JSON values serialize");
+        self.state.lock().unwrap().documents.insert(path.to_string(), ("application/json", bytes));
+        format!("{}{}", self.address, path)
+    }
+
+    /// Answers invocations matching `matcher` with `response`. Rules are tried in the order they
+    /// were added.
+    pub fn respond(&self, matcher: Match, response: Response) -> &Self {
+        self.state.lock().unwrap().rules.push((matcher, response));
+        self
+    }
+
+    /// Returns the invocations received so far, oldest first.
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.state.lock().unwrap().invocations.clone()
+    }
+}
+
+/// Answers a single HTTP request, then closes the connection.
+fn handle(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') &&
+            name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let (status, content_type, payload) = match (method, path) {
+        ("POST", "/covit") => {
+            let invocation: Invocation = serde_json::from_slice(&body)?;
+            let mut state = state.lock().unwrap();
+            let response = state
+                .rules
+                .iter()
+                .find(|(matcher, _)| matcher.matches(&invocation))
+                .map_or(Response::NotFound, |(_, response)| response.clone());
+            state.invocations.push(invocation);
+            ("200 OK", "application/json", serde_json::to_vec(&response)?)
+        }
+        ("GET", path) => {
+            let path = path.split_once('?').map_or(path, |(path, _)| path);
+            match state.lock().unwrap().documents.get(path).cloned() {
+                Some((content_type, bytes)) => ("200 OK", content_type, bytes),
+                None => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+            }
+        }
+        _ => ("405 Method Not Allowed", "text/plain", b"Method not allowed".to_vec()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        payload.len()
+    )?;
+    stream.write_all(&payload)?;
+    stream.flush()
+}
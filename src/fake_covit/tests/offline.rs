@@ -0,0 +1,461 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use fake_covit::protocol::Response;
+use fake_covit::server::{FakeCov, Match};
+use serde_json::json;
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::tag::{Accessor, ItemKey, Tag, TagType};
+use rusty_cov::cover_art_archive::{CoverArtArchiveSource, CoverArtSize};
+use rusty_cov::error::RustyCovError;
+use rusty_cov::lofty::{Provenance, SkipExisting, StripPolicy, embedded_picture_stats, front_cover, remove_embedded_art_from_file};
+use rusty_cov::observer::RunObserver;
+use rusty_cov::options::{RunOptions, RunOptionsBuilder};
+use rusty_cov::rank::RankingRules;
+use rusty_cov::report::{Outcome, RunReport};
+use rusty_cov::scan::ArtIssue;
+use rusty_cov::source::{CoverLookup, CoverQuery, CoverSource};
+use rusty_cov::structs::{CoverInfo, Picked, ReleaseInfo};
+use rusty_cov::{run_with, scan};
+
+const FAKE_COVIT: &str = env!("CARGO_BIN_EXE_fake_covit");
+
+/// 2x2 red PNG.
+const COVER: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00,
+    0x00, 0x00, 0x02, 0x08, 0x02, 0x00, 0x00, 0x00, 0xfd, 0xd4, 0x9a, 0x73, 0x00, 0x00, 0x00, 0x10, 0x49, 0x44, 0x41, 0x54, 0x78,
+    0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0x00, 0x44, 0x0c, 0x10, 0x0a, 0x00, 0x1f, 0xee, 0x03, 0xfd, 0x8b, 0x5f, 0x14, 0xd4, 0x00, 0x00,
+    0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+/// Creates an empty directory for the test `name` below the system temp directory.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fake_covit_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a silent WAV file tagged with `artist` and `album`.
+fn write_track(path: &Path, artist: &str, album: &str) {
+    let samples = [0u8; 8];
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&8000u32.to_le_bytes());
+    wav.extend_from_slice(&16000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&samples);
+    std::fs::write(path, wav).unwrap();
+
+    let mut tagged_file = lofty::read_from_path(path).unwrap();
+    let mut tag = Tag::new(TagType::Id3v2);
+    tag.set_artist(artist.to_string());
+    tag.set_album(album.to_string());
+    tag.insert_text(ItemKey::AlbumArtist, artist.to_string());
+    tagged_file.insert_tag(tag);
+    tagged_file.save_to_path(path, WriteOptions::default()).unwrap();
+}
+
+/// Writes silent MPEG audio with an ID3v2 tag embedding a picture of every one of `types`, and an
+/// APE tag carrying the album only (lofty exposes APE pictures as binary items, not pictures).
+fn write_multi_tag_mp3(path: &Path, types: &[PictureType]) {
+    // MPEG-1 Layer III, 128 kbit/s, 44.1 kHz frames of 417 bytes
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+    std::fs::write(path, frame.repeat(4)).unwrap();
+
+    let mut tagged_file = lofty::read_from_path(path).unwrap();
+    for tag_type in [TagType::Id3v2, TagType::Ape] {
+        let mut tag = Tag::new(tag_type);
+        tag.set_album("Album".to_string());
+        if tag_type == TagType::Id3v2 {
+            for picture_type in types {
+                tag.push_picture(Picture::new_unchecked(*picture_type, Some(MimeType::Png), None, COVER.to_vec()));
+            }
+        }
+        tagged_file.insert_tag(tag);
+    }
+    tagged_file.save_to_path(path, WriteOptions::default()).unwrap();
+}
+
+/// Writes a minimal M4A file, an audio track without samples, embedding `pictures` covers.
+fn write_m4a(path: &Path, pictures: usize) {
+    fn atom(name: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut atom = (8 + content.len() as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(name);
+        atom.extend_from_slice(content);
+        atom
+    }
+
+    let mdhd = atom(b"mdhd", &[[0u8; 12].as_slice(), &8000u32.to_be_bytes(), &[0u8; 8]].concat());
+    let hdlr = atom(b"hdlr", &[[0u8; 8].as_slice(), b"soun", &[0u8; 13]].concat());
+    let moov = atom(b"moov", &atom(b"trak", &atom(b"mdia", &[mdhd, hdlr].concat())));
+    let ftyp = atom(b"ftyp", b"M4A \0\0\0\0M4A isom");
+    std::fs::write(path, [ftyp, moov, atom(b"mdat", &[])].concat()).unwrap();
+
+    let mut tagged_file = lofty::read_from_path(path).unwrap();
+    let mut tag = Tag::new(TagType::Mp4Ilst);
+    for _ in 0..pictures {
+        tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, Some(MimeType::Png), None, COVER.to_vec()));
+    }
+    tagged_file.insert_tag(tag);
+    tagged_file.save_to_path(path, WriteOptions::default()).unwrap();
+}
+
+/// Adds a MusicBrainz release ID tag to the track at `path`.
+fn tag_release_id(path: &Path, release_id: &str) {
+    let mut tagged_file = lofty::read_from_path(path).unwrap();
+    tagged_file.primary_tag_mut().unwrap().insert_text(ItemKey::MusicBrainzReleaseId, release_id.to_string());
+    tagged_file.save_to_path(path, WriteOptions::default()).unwrap();
+}
+
+/// Embeds a picture of each of `types` into the track at `path`.
+fn embed_pictures(path: &Path, types: &[PictureType]) {
+    let mut tagged_file = lofty::read_from_path(path).unwrap();
+    let tag = tagged_file.primary_tag_mut().unwrap();
+    for picture_type in types {
+        tag.push_picture(Picture::new_unchecked(*picture_type, Some(MimeType::Png), None, COVER.to_vec()));
+    }
+    tagged_file.save_to_path(path, WriteOptions::default()).unwrap();
+}
+
+/// Creates placeholder ffmpeg and ffprobe binaries, so that runs with the `depend-on-ffmpeg`
+/// feature do not download them. The tests never run ffmpeg.
+fn ffmpeg_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fake_covit_ffmpeg_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for bin in ["ffmpeg", "ffprobe"] {
+        std::fs::write(dir.join(format!("{}{}", bin, std::env::consts::EXE_SUFFIX)), b"").unwrap();
+    }
+    dir
+}
+
+fn options(cov: &FakeCov, input: &Path) -> RunOptionsBuilder {
+    RunOptions::builder()
+        .input(input.to_string_lossy())
+        .covit_path(FAKE_COVIT)
+        .ffmpeg_dir(ffmpeg_dir())
+        .cov_address(cov.address())
+        .jobs(1)
+}
+
+fn file_outcome(report: &RunReport, path: &Path) -> Outcome {
+    let file = report.directories.iter().flat_map(|dir| &dir.files).find(|file| file.path == path);
+    file.unwrap_or_else(|| panic!("{:?} is not in the report", path)).outcome.clone()
+}
+
+#[test]
+fn per_file_mode_reuses_the_pick_of_an_album() {
+    let cov = FakeCov::start().unwrap();
+    let url = cov.image("cover.png", COVER.to_vec());
+    cov.respond(Match::Any, Response::pick(&url));
+
+    let dir = test_dir("per_file");
+    let tracks = [dir.join("01 First.wav"), dir.join("02 Second.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+
+    let report = run_with(&options(&cov, &dir).build()).unwrap();
+
+    assert_eq!(cov.invocations().len(), 1);
+    assert_eq!(report.summary.files_edited, 2);
+    for track in &tracks {
+        assert_eq!(file_outcome(&report, track), Outcome::Embedded);
+        assert_eq!(embedded_picture_stats(track).unwrap(), (1, COVER.len() as u64));
+    }
+}
+
+#[test]
+fn album_mode_saves_the_cover_next_to_the_tracks() {
+    let cov = FakeCov::start().unwrap();
+    let url = cov.image("cover.png", COVER.to_vec());
+    cov.respond(Match::Any, Response::pick(&url));
+
+    let dir = test_dir("album");
+    let tracks = [dir.join("01 Covered.wav"), dir.join("02 Bare.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+    embed_pictures(&tracks[0], &[PictureType::CoverFront]);
+
+    let report = run_with(&options(&cov, &dir).album_folder_mode("cover").build()).unwrap();
+
+    assert_eq!(report.directories[0].outcome, Outcome::SidecarSaved);
+    assert_eq!(std::fs::read(dir.join("cover.png")).unwrap(), COVER);
+    assert_eq!(embedded_picture_stats(&tracks[0]).unwrap().0, 0);
+    assert_eq!(file_outcome(&report, &tracks[0]), Outcome::Stripped);
+    assert_eq!(file_outcome(&report, &tracks[1]), Outcome::Unchanged);
+    assert_eq!(report.summary.files_edited, 1);
+}
+
+#[test]
+fn strip_policies_keep_the_other_tags_of_a_file() {
+    let dir = test_dir("strip_policy");
+    let types = [PictureType::CoverFront, PictureType::CoverBack, PictureType::Leaflet, PictureType::Artist];
+    let cases = [(StripPolicy::FrontCover, 3), (StripPolicy::KeepBookletAndBack, 2), (StripPolicy::All, 0)];
+
+    for (policy, left) in cases {
+        let track = dir.join(format!("{}.mp3", policy));
+        write_multi_tag_mp3(&track, &types);
+        assert_eq!(embedded_picture_stats(&track).unwrap().0, types.len());
+
+        assert_eq!(remove_embedded_art_from_file(&track, policy).unwrap(), types.len() - left);
+
+        let tagged_file = lofty::read_from_path(&track).unwrap();
+        let kept: Vec<PictureType> = tagged_file.tag(TagType::Id3v2).unwrap().pictures().iter().map(Picture::pic_type).collect();
+        let expected: Vec<PictureType> = types.into_iter().filter(|picture_type| !policy.strips(FileType::Mpeg, *picture_type)).collect();
+        assert_eq!(kept, expected, "{}", policy);
+        // The other tag of the file survives the rewrite
+        assert_eq!(tagged_file.tag(TagType::Ape).unwrap().album().as_deref(), Some("Album"), "{}", policy);
+    }
+}
+
+#[test]
+fn m4a_pictures_count_as_front_covers() {
+    let dir = test_dir("m4a");
+    let track = dir.join("01 First.m4a");
+    write_m4a(&track, 1);
+    assert_eq!(front_cover(&lofty::read_from_path(&track).unwrap()).map(Picture::pic_type), Some(PictureType::Other));
+
+    assert_eq!(remove_embedded_art_from_file(&track, StripPolicy::FrontCover).unwrap(), 1);
+    assert_eq!(embedded_picture_stats(&track).unwrap().0, 0);
+}
+
+#[test]
+fn extract_mode_writes_the_largest_embedded_front_cover() {
+    // Same PNG with a header claiming 600x600
+    let mut large = COVER.to_vec();
+    large[16..24].copy_from_slice(&[0, 0, 2, 0x58, 0, 0, 2, 0x58]);
+
+    let dir = test_dir("extract");
+    let tracks = [dir.join("01 First.wav"), dir.join("02 Second.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+    embed_pictures(&tracks[0], &[PictureType::CoverFront]);
+    let mut tagged_file = lofty::read_from_path(&tracks[1]).unwrap();
+    let tag = tagged_file.primary_tag_mut().unwrap();
+    tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, Some(MimeType::Png), None, large.clone()));
+    tag.push_picture(Picture::new_unchecked(PictureType::CoverBack, Some(MimeType::Png), None, COVER.to_vec()));
+    tagged_file.save_to_path(&tracks[1], WriteOptions::default()).unwrap();
+
+    let options = RunOptions::builder().input(dir.to_string_lossy()).extract("cover").strip_extracted(true).build();
+    let report = run_with(&options).unwrap();
+
+    assert_eq!(report.directories[0].outcome, Outcome::SidecarSaved);
+    assert_eq!(std::fs::read(dir.join("cover.png")).unwrap(), large);
+    assert_eq!(embedded_picture_stats(&tracks[0]).unwrap().0, 0);
+    assert_eq!(embedded_picture_stats(&tracks[1]).unwrap().0, 1);
+}
+
+#[test]
+fn scan_reports_inconsistent_and_duplicated_art_without_writing() {
+    let dir = test_dir("scan");
+    let tracks = [dir.join("01 First.wav"), dir.join("02 Second.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+    embed_pictures(&tracks[0], &[PictureType::CoverFront, PictureType::Leaflet]);
+    std::fs::write(dir.join("folder.png"), COVER).unwrap();
+    let modified = std::fs::metadata(&tracks[0]).unwrap().modified().unwrap();
+
+    let scan = scan(&RunOptions::builder().input(dir.to_string_lossy()).scan(true).build()).unwrap();
+
+    let directory = &scan.directories[0];
+    assert_eq!(directory.sidecar.as_ref().map(|sidecar| (sidecar.width, sidecar.height)), Some((Some(2), Some(2))));
+    assert_eq!(directory.issues, [ArtIssue::LowResolution, ArtIssue::Inconsistent, ArtIssue::SidecarAndEmbedded]);
+    let file = directory.files.iter().find(|file| file.path == tracks[0]).unwrap();
+    let types: Vec<&str> = file.pictures.iter().map(|picture| picture.picture_type.as_str()).collect();
+    assert_eq!(types, ["CoverFront", "Leaflet"]);
+    assert_eq!(file.pictures[0].mime_type.as_deref(), Some("image/png"));
+    assert!(scan.to_csv().lines().nth(1).unwrap().contains("low resolution;inconsistent;sidecar and embedded"));
+    assert_eq!(std::fs::metadata(&tracks[0]).unwrap().modified().unwrap(), modified);
+}
+
+#[test]
+fn skip_existing_leaves_files_with_good_enough_art_alone() {
+    let cov = FakeCov::start().unwrap();
+    let url = cov.image("cover.png", COVER.to_vec());
+    cov.respond(Match::Any, Response::pick(&url));
+
+    let dir = test_dir("skip_existing");
+    let tracks = [dir.join("01 Covered.wav"), dir.join("02 Bare.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+    embed_pictures(&tracks[0], &[PictureType::CoverFront]);
+
+    let report = run_with(&options(&cov, &dir).ask_per_file(true).skip_existing(SkipExisting::AnyFrontCover).build()).unwrap();
+
+    assert_eq!(file_outcome(&report, &tracks[0]), Outcome::SkippedExistingArt);
+    assert_eq!(file_outcome(&report, &tracks[1]), Outcome::Embedded);
+    assert_eq!((report.summary.files_edited, report.summary.files_skipped), (1, 1));
+    assert_eq!(cov.invocations().len(), 1);
+
+    // Only the cover embedded by the run carries a provenance marker
+    let since: SkipExisting = format!("since:{}", Provenance::now().embedded_at - 60).parse().unwrap();
+    let report = run_with(&options(&cov, &dir).ask_per_file(true).skip_existing(since).build()).unwrap();
+
+    assert_eq!(file_outcome(&report, &tracks[0]), Outcome::Embedded);
+    assert_eq!(file_outcome(&report, &tracks[1]), Outcome::SkippedExistingArt);
+}
+
+#[test]
+fn falls_back_to_the_album_tags() {
+    let cov = FakeCov::start().unwrap();
+    let url = cov.image("cover.png", COVER.to_vec());
+    cov.respond(Match::Album("Album".to_string()), Response::pick(&url));
+
+    let dir = test_dir("fallback");
+    let track = dir.join("01 First.wav");
+    write_track(&track, "Artist", "Album");
+
+    let report = run_with(&options(&cov, &dir).build()).unwrap();
+
+    let invocations = cov.invocations();
+    assert_eq!(invocations.len(), 2);
+    assert_eq!(invocations[0].input.as_deref(), Some(track.as_path()));
+    assert_eq!(invocations[1].query_album.as_deref(), Some("Album"));
+    assert_eq!(invocations[1].query_artist.as_deref(), Some("Artist"));
+    assert_eq!(file_outcome(&report, &track), Outcome::Embedded);
+}
+
+#[test]
+fn reports_closed_tabs_crashes_and_timeouts() {
+    let cov = FakeCov::start().unwrap();
+    cov.respond(Match::Input("Closed".to_string()), Response::Close)
+        .respond(Match::Input("Crash".to_string()), Response::Crash { status: 3, stderr: "boom".to_string() })
+        .respond(Match::Input("Hang".to_string()), Response::Hang);
+
+    let dir = test_dir("failures");
+    let tracks = [dir.join("01 Closed.wav"), dir.join("02 Crash.wav"), dir.join("03 Hang.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+
+    let options = options(&cov, &dir).ask_per_file(true).pick_timeout(Some(Duration::from_secs(1))).build();
+    let report = run_with(&options).unwrap();
+
+    assert_eq!(file_outcome(&report, &tracks[0]), Outcome::UserClosedTab);
+    assert!(matches!(file_outcome(&report, &tracks[1]), Outcome::Error { message } if message.contains("boom")));
+    assert_eq!(file_outcome(&report, &tracks[2]), Outcome::PickTimedOut);
+    assert_eq!(report.summary.files_edited, 0);
+}
+
+#[test]
+fn rejects_jpeg_qualities_out_of_range() {
+    let cov = FakeCov::start().unwrap();
+    let dir = test_dir("jpeg_quality");
+    write_track(&dir.join("01 First.wav"), "Artist", "Album");
+
+    for quality in [0, 101] {
+        let error = run_with(&options(&cov, &dir).jpeg_optimise(Some(quality)).build()).unwrap_err();
+        assert!(matches!(error, RustyCovError::InvalidJpegQuality { quality: q } if q == quality));
+    }
+    assert!(cov.invocations().is_empty());
+}
+
+/// Cover source offering a single 1200x1200 candidate titled after the file name.
+struct TitleSource {
+    url: String,
+}
+
+impl CoverSource for TitleSource {
+    fn find_cover(&self, query: &CoverQuery, _observer: &dyn RunObserver) -> Result<CoverLookup, RustyCovError> {
+        let title = if query.path.to_string_lossy().contains("Mismatch") { "Something Else Entirely" } else { "Album" };
+        Ok(CoverLookup::Candidates(vec![Picked {
+            source: Some("fake".to_string()),
+            big_cover_url: self.url.clone(),
+            release_info: ReleaseInfo { title: Some(title.to_string()), artist: Some("Artist".to_string()), ..ReleaseInfo::default() },
+            cover_info: CoverInfo { width: Some(1200), height: Some(1200), ..CoverInfo::default() },
+            ..Picked::default()
+        }]))
+    }
+}
+
+#[test]
+fn auto_pick_defers_covers_that_do_not_match_the_tags() {
+    let cov = FakeCov::start().unwrap();
+    let url = cov.image("cover.png", COVER.to_vec());
+
+    let dir = test_dir("auto_pick");
+    let tracks = [dir.join("01 Match.wav"), dir.join("02 Mismatch.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+
+    // covit asks the user, whose pick must not be deferred
+    let error = run_with(&options(&cov, &dir).auto_pick(RankingRules::default()).build()).unwrap_err();
+    assert!(matches!(error, RustyCovError::AutoPickNeedsAutomaticSource));
+
+    let source = TitleSource { url };
+    let options = options(&cov, &dir).cover_source(Arc::new(source)).ask_per_file(true).auto_pick(RankingRules::default());
+    let report = run_with(&options.build()).unwrap();
+
+    let files = &report.directories[0].files;
+    assert_eq!(file_outcome(&report, &tracks[0]), Outcome::Embedded);
+    assert_eq!(file_outcome(&report, &tracks[1]), Outcome::Deferred);
+    assert!(files.iter().all(|file| file.score.is_some()));
+    assert_eq!(embedded_picture_stats(&tracks[1]).unwrap().0, 0);
+    assert!(cov.invocations().is_empty());
+}
+
+#[test]
+fn cover_art_archive_looks_up_the_tagged_release() {
+    const RELEASE_ID: &str = "76df3287-6cda-33eb-8e9a-044b5e15ffdd";
+
+    let cov = FakeCov::start().unwrap();
+    let original = cov.image("front.png", COVER.to_vec());
+    let medium = cov.image("front-500.png", COVER.to_vec());
+    cov.json(
+        &format!("/caa/release/{}", RELEASE_ID),
+        &json!({ "images": [
+            { "image": "http://127.0.0.1:9/back.png", "front": false, "types": ["Back"], "thumbnails": {} },
+            { "image": original, "front": true, "types": ["Front"], "thumbnails": { "500": medium } },
+        ] }),
+    );
+    cov.json(
+        &format!("/mb/ws/2/release/{}", RELEASE_ID),
+        &json!({
+            "title": "Album",
+            "date": "2001-05-01",
+            "artist-credit": [{ "name": "Artist", "joinphrase": " & " }, { "name": "Guest", "joinphrase": "" }],
+            "media": [{ "track-count": 10 }, { "track-count": 2 }],
+        }),
+    );
+
+    let dir = test_dir("cover_art_archive");
+    let tracks = [dir.join("01 Tagged.wav"), dir.join("02 Untagged.wav")];
+    for track in &tracks {
+        write_track(track, "Artist", "Album");
+    }
+    tag_release_id(&tracks[0], RELEASE_ID);
+
+    let source = CoverArtArchiveSource::new(format!("{}/caa", cov.address()))
+        .musicbrainz_url(Some(format!("{}/mb", cov.address())))
+        .size(CoverArtSize::Medium);
+    let options = RunOptions::builder().input(dir.to_string_lossy()).cover_source(Arc::new(source)).ask_per_file(true).build();
+    let report = run_with(&options).unwrap();
+
+    let tagged = report.directories[0].files.iter().find(|file| file.path == tracks[0]).unwrap();
+    assert_eq!(tagged.outcome, Outcome::Embedded);
+    let picked = tagged.picked.as_ref().unwrap();
+    assert_eq!(picked.big_cover_url, medium);
+    assert_eq!(picked.original_cover_url.as_deref(), Some(original.as_str()));
+    assert_eq!(picked.release_info.artist.as_deref(), Some("Artist & Guest"));
+    assert_eq!(picked.release_info.tracks, Some(12));
+    assert_eq!(file_outcome(&report, &tracks[1]), Outcome::NoCoverFound);
+    assert!(cov.invocations().is_empty());
+}
@@ -0,0 +1,446 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lofty::config::WriteOptions;
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::prelude::*;
+use lofty::tag::Tag;
+use serde::{Deserialize, Serialize};
+
+use crate::error::RustyCovError;
+use crate::helpers::{content_hash, content_hash_of_reader};
+use crate::lofty::{read_tagged_file, tag_type_from_name, tag_type_name};
+use crate::observer::RunObserver;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const PICTURES_DIR: &str = "pictures";
+
+/// A picture saved from a tag before it was modified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PictureBackup {
+    /// ID3v2 picture type code, see [`PictureType::as_u8`].
+    pub picture_type: u8,
+    pub mime_type: Option<String>,
+    pub description: Option<String>,
+    /// Name of the blob holding the picture bytes, stored as `pictures/<data_hash>.bin`: the
+    /// content hash of the bytes, suffixed with `-<n>` when different bytes share a hash.
+    pub data_hash: String,
+}
+
+/// The picture block of a single tag before it was modified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagBackup {
+    /// Tag type name, see [`tag_type_name`].
+    pub tag_type: String,
+    pub pictures: Vec<PictureBackup>,
+}
+
+/// The original pictures of a single audio file, keyed by its path and content hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileBackup {
+    pub path: PathBuf,
+    /// Content hash of the file before it was first modified in the session.
+    pub content_hash: String,
+    /// Content hash of the file after the session last modified it. `None` if the session never
+    /// finished writing it.
+    #[serde(default)]
+    pub session_hash: Option<String>,
+    pub tags: Vec<TagBackup>,
+}
+
+/// Everything a session changed, as stored in `manifest.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub session: String,
+    pub files: Vec<FileBackup>,
+    /// Cover files written by the session, deleted on undo.
+    pub created_sidecars: Vec<PathBuf>,
+}
+
+/// Counts reported by [`undo`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UndoSummary {
+    pub files_restored: usize,
+    pub sidecars_removed: usize,
+}
+
+/// Backup store for a single session.
+///
+/// Each session lives in its own directory below the store root, holding a `manifest.json` and the
+/// picture bytes. [`BackupStore::backup_file`] must be called before a file is modified; only the
+/// first call per file is recorded, so the manifest always holds the original state.
+#[derive(Debug)]
+pub struct BackupStore {
+    session_dir: PathBuf,
+    manifest: Mutex<BackupManifest>,
+}
+
+impl BackupStore {
+    /// Creates a new session directory below `root`, named `<secs>-<pid>` and suffixed with
+    /// `-<n>` when a session of that name already exists.
+    pub fn create(root: &Path) -> Result<Self, RustyCovError> {
+        std::fs::create_dir_all(root).map_err(|source| RustyCovError::Io { path: root.to_path_buf(), source })?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let base = format!("{}-{}", now.as_secs(), std::process::id());
+        for n in 0.. {
+            let session = if n == 0 { base.clone() } else { format!("{}-{}", base, n) };
+            let session_dir = root.join(&session);
+            // Creating the directory itself claims the name, so concurrent runs never share it
+            match std::fs::create_dir(&session_dir) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(source) => return Err(RustyCovError::Io { path: session_dir, source }),
+            }
+            let pictures_dir = session_dir.join(PICTURES_DIR);
+            std::fs::create_dir(&pictures_dir).map_err(|source| RustyCovError::Io { path: pictures_dir, source })?;
+
+            let store = Self { session_dir, manifest: Mutex::new(BackupManifest { session, ..BackupManifest::default() }) };
+            store.save_manifest(&store.manifest.lock().unwrap())?;
+            return Ok(store);
+        }
+        unreachable!("the session names are unbounded")
+    }
+
+    /// Returns the directory of this session, which is passed to [`undo`].
+    pub fn session_dir(&self) -> &Path {
+        &self.session_dir
+    }
+
+    /// Saves every picture of every tag in `path` unless the file was already backed up.
+    pub fn backup_file(&self, path: &Path) -> Result<(), RustyCovError> {
+        if self.manifest.lock().unwrap().files.iter().any(|file| file.path == path) {
+            return Ok(());
+        }
+
+        let content_hash = file_hash(path)?;
+        let tagged_file = read_tagged_file(path)?;
+
+        let mut tags = Vec::new();
+        for tag in tagged_file.tags() {
+            let mut pictures = Vec::new();
+            for picture in tag.pictures() {
+                let data_hash = self.store_blob(picture.data())?;
+                pictures.push(PictureBackup {
+                    picture_type: picture.pic_type().as_u8(),
+                    mime_type: picture.mime_type().map(|mime| mime.as_str().to_string()),
+                    description: picture.description().map(str::to_string),
+                    data_hash,
+                });
+            }
+            tags.push(TagBackup { tag_type: tag_type_name(tag.tag_type()).to_string(), pictures });
+        }
+
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.files.push(FileBackup { path: path.to_path_buf(), content_hash, session_hash: None, tags });
+        self.save_manifest(&manifest)
+    }
+
+    /// Records the contents of `path` after the session modified it, so that [`undo`] can tell
+    /// whether the file changed since.
+    pub fn record_modified(&self, path: &Path) -> Result<(), RustyCovError> {
+        let session_hash = file_hash(path)?;
+        let mut manifest = self.manifest.lock().unwrap();
+        if let Some(file) = manifest.files.iter_mut().find(|file| file.path == path) {
+            file.session_hash = Some(session_hash);
+        }
+        self.save_manifest(&manifest)
+    }
+
+    /// Stores `data` as a picture blob unless identical bytes are already stored, and returns the
+    /// blob name. Blobs whose hash matches but whose bytes differ get a numbered name instead of
+    /// being reused.
+    fn store_blob(&self, data: &[u8]) -> Result<String, RustyCovError> {
+        let hash = content_hash(data);
+        for n in 0.. {
+            let name = if n == 0 { hash.clone() } else { format!("{}-{}", hash, n) };
+            let data_path = self.session_dir.join(PICTURES_DIR).join(format!("{}.bin", name));
+            match std::fs::read(&data_path) {
+                Ok(stored) if stored == data => return Ok(name),
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    std::fs::write(&data_path, data).map_err(|source| RustyCovError::Io { path: data_path, source })?;
+                    return Ok(name);
+                }
+                Err(source) => return Err(RustyCovError::Io { path: data_path, source }),
+            }
+        }
+        unreachable!("the blob names are unbounded")
+    }
+
+    /// Records a cover file created by the session so that [`undo`] deletes it.
+    pub fn record_sidecar(&self, path: &Path) -> Result<(), RustyCovError> {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.created_sidecars.push(path.to_path_buf());
+        self.save_manifest(&manifest)
+    }
+
+    fn save_manifest(&self, manifest: &BackupManifest) -> Result<(), RustyCovError> {
+        let path = self.session_dir.join(MANIFEST_FILE);
+        let json = serde_json::to_vec_pretty(manifest).map_err(|e| RustyCovError::Io { path: path.clone(), source: e.into() })?;
+        std::fs::write(&path, json).map_err(|source| RustyCovError::Io { path, source })
+    }
+}
+
+/// Returns the content hash of the file at `path`, read in chunks.
+fn file_hash(path: &Path) -> Result<String, RustyCovError> {
+    let file = std::fs::File::open(path).map_err(|source| RustyCovError::Io { path: path.to_path_buf(), source })?;
+    content_hash_of_reader(file).map_err(|source| RustyCovError::Io { path: path.to_path_buf(), source })
+}
+
+/// Reads the manifest of the session stored in `session_dir`.
+pub fn read_manifest(session_dir: &Path) -> Result<BackupManifest, RustyCovError> {
+    let path = session_dir.join(MANIFEST_FILE);
+    let json = std::fs::read(&path).map_err(|source| RustyCovError::Io { path: path.clone(), source })?;
+    serde_json::from_slice(&json).map_err(|e| RustyCovError::Io { path, source: e.into() })
+}
+
+/// Restores the pictures saved in the session at `session_dir` and deletes the cover files the
+/// session created.
+///
+/// Every tag of a restored file gets exactly the pictures it had before the session; tags that
+/// did not exist then lose any pictures added since. Files whose contents changed after the session
+/// wrote them are left alone and reported as [`RustyCovError::BackupMismatch`]. A failure for one
+/// file is reported to `observer` and the remaining files are still restored.
+///
+/// # Arguments
+///
+/// * `session_dir` - Session directory, see [`BackupStore::session_dir`].
+/// * `observer` - Receives the errors for individual files.
+pub fn undo(session_dir: &Path, observer: &dyn RunObserver) -> Result<UndoSummary, RustyCovError> {
+    let manifest = read_manifest(session_dir)?;
+    let mut summary = UndoSummary::default();
+
+    for file in &manifest.files {
+        match restore_file(session_dir, file) {
+            Ok(()) => summary.files_restored += 1,
+            Err(e) => observer.error(&e),
+        }
+    }
+
+    for sidecar in &manifest.created_sidecars {
+        match std::fs::remove_file(sidecar) {
+            Ok(()) => summary.sidecars_removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(source) => observer.error(&RustyCovError::Io { path: sidecar.clone(), source }),
+        }
+    }
+
+    Ok(summary)
+}
+
+fn restore_file(session_dir: &Path, file: &FileBackup) -> Result<(), RustyCovError> {
+    // The file may be unchanged if the session failed to write it
+    let current_hash = file_hash(&file.path)?;
+    if file.session_hash.as_ref() != Some(&current_hash) && current_hash != file.content_hash {
+        return Err(RustyCovError::BackupMismatch { path: file.path.clone() });
+    }
+
+    let mut tagged_file = read_tagged_file(&file.path)?;
+
+    let mut restored_types = Vec::new();
+    for tag_backup in &file.tags {
+        let Some(tag_type) = tag_type_from_name(&tag_backup.tag_type) else {
+            continue;
+        };
+        if tagged_file.tag(tag_type).is_none() {
+            tagged_file.insert_tag(Tag::new(tag_type));
+        }
+        let Some(tag) = tagged_file.tag_mut(tag_type) else {
+            continue;
+        };
+
+        while !tag.pictures().is_empty() {
+            tag.remove_picture(0);
+        }
+        for picture in &tag_backup.pictures {
+            let data_path = session_dir.join(PICTURES_DIR).join(format!("{}.bin", picture.data_hash));
+            let data = std::fs::read(&data_path).map_err(|source| RustyCovError::Io { path: data_path, source })?;
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::from_u8(picture.picture_type),
+                picture.mime_type.as_deref().map(MimeType::from_str),
+                picture.description.clone(),
+                data,
+            ));
+        }
+        restored_types.push(tag_type);
+    }
+
+    // Tags created by the session only held the new cover
+    let added_types: Vec<_> =
+        tagged_file.tags().iter().map(|tag| tag.tag_type()).filter(|tag_type| !restored_types.contains(tag_type)).collect();
+    for tag_type in added_types {
+        if let Some(tag) = tagged_file.tag_mut(tag_type) {
+            while !tag.pictures().is_empty() {
+                tag.remove_picture(0);
+            }
+        }
+    }
+
+    tagged_file
+        .save_to_path(&file.path, WriteOptions::new().respect_read_only(false))
+        .map_err(|source| RustyCovError::TagWrite { path: file.path.clone(), source })
+}
+
+#[cfg(test)]
+mod tests {
+    use lofty::tag::TagType;
+
+    use super::*;
+
+    /// Collects the errors reported by [`undo`].
+    #[derive(Default)]
+    struct ErrorCollector(Mutex<Vec<String>>);
+
+    impl RunObserver for ErrorCollector {
+        fn error(&self, error: &RustyCovError) {
+            self.0.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusty_cov_backup_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn picture(picture_type: PictureType, data: &[u8]) -> Picture {
+        Picture::new_unchecked(picture_type, Some(MimeType::Png), Some(format!("{:?}", picture_type)), data.to_vec())
+    }
+
+    /// Writes silent MPEG audio holding `tags`.
+    fn write_mp3(path: &Path, tags: Vec<Tag>) {
+        // MPEG-1 Layer III, 128 kbit/s, 44.1 kHz frames of 417 bytes
+        let mut frame = vec![0u8; 417];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        std::fs::write(path, frame.repeat(4)).unwrap();
+        edit_tags(path, |tagged_file| {
+            for tag in tags {
+                tagged_file.insert_tag(tag);
+            }
+        });
+    }
+
+    fn edit_tags(path: &Path, edit: impl FnOnce(&mut lofty::file::TaggedFile)) {
+        let mut tagged_file = read_tagged_file(path).unwrap();
+        edit(&mut tagged_file);
+        tagged_file.save_to_path(path, WriteOptions::default()).unwrap();
+    }
+
+    fn tag_with(tag_type: TagType, pictures: &[Picture]) -> Tag {
+        let mut tag = Tag::new(tag_type);
+        tag.set_album("Album".to_string());
+        pictures.iter().cloned().for_each(|picture| tag.push_picture(picture));
+        tag
+    }
+
+    /// Replaces the pictures of the `tag_type` tag of `path`, creating the tag if needed.
+    fn replace_pictures(path: &Path, tag_type: TagType, pictures: &[Picture]) {
+        edit_tags(path, |tagged_file| {
+            if tagged_file.tag(tag_type).is_none() {
+                tagged_file.insert_tag(Tag::new(tag_type));
+            }
+            let tag = tagged_file.tag_mut(tag_type).unwrap();
+            while !tag.pictures().is_empty() {
+                tag.remove_picture(0);
+            }
+            pictures.iter().cloned().for_each(|picture| tag.push_picture(picture));
+        });
+    }
+
+    fn pictures(path: &Path, tag_type: TagType) -> Vec<Picture> {
+        read_tagged_file(path).unwrap().tag(tag_type).map(|tag| tag.pictures().to_vec()).unwrap_or_default()
+    }
+
+    #[test]
+    fn sessions_never_share_a_directory() {
+        let root = test_dir("sessions").join("backups");
+        let first = BackupStore::create(&root).unwrap();
+        let second = BackupStore::create(&root).unwrap();
+
+        assert_ne!(first.session_dir(), second.session_dir());
+        for store in [&first, &second] {
+            let manifest = read_manifest(store.session_dir()).unwrap();
+            assert_eq!(store.session_dir().file_name().unwrap().to_str(), Some(manifest.session.as_str()));
+        }
+    }
+
+    #[test]
+    fn restores_the_pictures_of_a_multi_tag_file() {
+        let dir = test_dir("restore");
+        let track = dir.join("01 First.mp3");
+        let original = [picture(PictureType::CoverFront, b"front"), picture(PictureType::CoverBack, b"back")];
+        write_mp3(&track, vec![tag_with(TagType::Id3v2, &original), tag_with(TagType::Ape, &[])]);
+
+        let store = BackupStore::create(&dir.join("backups")).unwrap();
+        store.backup_file(&track).unwrap();
+        replace_pictures(&track, TagType::Id3v2, &[picture(PictureType::CoverFront, b"new")]);
+        store.record_modified(&track).unwrap();
+        // Only the first backup of a file is kept
+        store.backup_file(&track).unwrap();
+
+        let observer = ErrorCollector::default();
+        let summary = undo(store.session_dir(), &observer).unwrap();
+
+        assert_eq!(summary, UndoSummary { files_restored: 1, sidecars_removed: 0 });
+        assert!(observer.0.lock().unwrap().is_empty());
+        assert_eq!(pictures(&track, TagType::Id3v2), original);
+        assert_eq!(read_tagged_file(&track).unwrap().tag(TagType::Ape).unwrap().album().as_deref(), Some("Album"));
+    }
+
+    #[test]
+    fn clears_the_tags_the_session_created() {
+        let dir = test_dir("created");
+        let track = dir.join("01 First.mp3");
+        let sidecar = dir.join("cover.png");
+        write_mp3(&track, vec![tag_with(TagType::Ape, &[])]);
+
+        let store = BackupStore::create(&dir.join("backups")).unwrap();
+        store.backup_file(&track).unwrap();
+        replace_pictures(&track, TagType::Id3v2, &[picture(PictureType::CoverFront, b"new")]);
+        store.record_modified(&track).unwrap();
+        std::fs::write(&sidecar, b"new").unwrap();
+        store.record_sidecar(&sidecar).unwrap();
+
+        let summary = undo(store.session_dir(), &ErrorCollector::default()).unwrap();
+
+        assert_eq!(summary, UndoSummary { files_restored: 1, sidecars_removed: 1 });
+        assert!(pictures(&track, TagType::Id3v2).is_empty());
+        assert!(read_tagged_file(&track).unwrap().tag(TagType::Ape).is_some());
+        assert!(!sidecar.exists());
+    }
+
+    #[test]
+    fn leaves_files_changed_after_the_session_alone() {
+        let dir = test_dir("mismatch");
+        let track = dir.join("01 First.mp3");
+        write_mp3(&track, vec![tag_with(TagType::Id3v2, &[picture(PictureType::CoverFront, b"front")])]);
+
+        let store = BackupStore::create(&dir.join("backups")).unwrap();
+        store.backup_file(&track).unwrap();
+        replace_pictures(&track, TagType::Id3v2, &[picture(PictureType::CoverFront, b"new")]);
+        store.record_modified(&track).unwrap();
+        edit_tags(&track, |tagged_file| tagged_file.tag_mut(TagType::Id3v2).unwrap().set_album("Edited".to_string()));
+
+        let observer = ErrorCollector::default();
+        let summary = undo(store.session_dir(), &observer).unwrap();
+
+        assert_eq!(summary.files_restored, 0);
+        assert_eq!(*observer.0.lock().unwrap(), [RustyCovError::BackupMismatch { path: track.clone() }.to_string()]);
+        assert_eq!(pictures(&track, TagType::Id3v2), [picture(PictureType::CoverFront, b"new")]);
+    }
+
+    #[test]
+    fn numbers_blobs_whose_hash_is_taken_by_other_bytes() {
+        let store = BackupStore::create(&test_dir("blobs")).unwrap();
+        let hash = content_hash(b"cover");
+        // Stands in for different bytes with the same hash
+        std::fs::write(store.session_dir().join(PICTURES_DIR).join(format!("{}.bin", hash)), b"other").unwrap();
+
+        let name = store.store_blob(b"cover").unwrap();
+        assert_eq!(name, format!("{}-1", hash));
+        assert_eq!(std::fs::read(store.session_dir().join(PICTURES_DIR).join(format!("{}.bin", name))).unwrap(), b"cover");
+        assert_eq!(store.store_blob(b"cover").unwrap(), name);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::DownloadedCover;
+use crate::error::RustyCovError;
+use crate::source::CoverQuery;
+use crate::structs::Picked;
+
+/// Identity of the album a file belongs to in per-file mode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum AlbumKey {
+    /// Album artist and album tags, compared case-insensitively.
+    Tags { album_artist: String, album: String },
+    /// Directory of the file, along with its album tag if it has one.
+    Dir { dir: PathBuf, album: Option<String> },
+}
+
+impl AlbumKey {
+    /// Returns the key for the file queried by `query`, using the directory when the album
+    /// artist or album tag is missing.
+    pub(crate) fn for_query(query: &CoverQuery) -> Self {
+        match (&query.album_artist, &query.album) {
+            (Some(album_artist), Some(album)) => {
+                AlbumKey::Tags { album_artist: album_artist.trim().to_lowercase(), album: album.trim().to_lowercase() }
+            }
+            _ => AlbumKey::Dir {
+                dir: query.path.parent().map(Path::to_path_buf).unwrap_or_default(),
+                album: query.album.as_ref().map(|album| album.trim().to_lowercase()),
+            },
+        }
+    }
+}
+
+/// Downloaded cover shared by the jobs that still need it.
+#[derive(Default)]
+struct SharedDownload {
+    cover: Option<DownloadedCover>,
+    /// Jobs reserved with [`CoverCache::reserve`] that have not fetched the bytes yet.
+    pending: usize,
+}
+
+/// Picks and downloaded covers shared by the tracks of an album in per-file mode.
+///
+/// Picks are kept for the whole run. Downloaded bytes are kept only while a job reserved for the
+/// same URL is still waiting, so at most the covers of the albums in flight are held in memory.
+#[derive(Default)]
+pub(crate) struct CoverCache {
+    picks: Mutex<HashMap<AlbumKey, Picked>>,
+    downloads: Mutex<HashMap<String, Arc<Mutex<SharedDownload>>>>,
+}
+
+impl CoverCache {
+    /// Returns the cover picked earlier for the album `key`.
+    pub(crate) fn picked(&self, key: &AlbumKey) -> Option<Picked> {
+        self.picks.lock().unwrap().get(key).cloned()
+    }
+
+    /// Remembers `picked` for the album `key`.
+    pub(crate) fn insert_pick(&self, key: AlbumKey, picked: Picked) {
+        self.picks.lock().unwrap().insert(key, picked);
+    }
+
+    /// Registers a job that will call [`CoverCache::fetch`] for `url`.
+    pub(crate) fn reserve(&self, url: &str) {
+        let entry = Arc::clone(self.downloads.lock().unwrap().entry(url.to_string()).or_default());
+        entry.lock().unwrap().pending += 1;
+    }
+
+    /// Returns the cover of the pick whose big cover is `url`, running `download` if no other job
+    /// has downloaded it yet. Jobs for the same pick wait for a download in progress instead of
+    /// starting their own.
+    pub(crate) fn fetch<F>(&self, url: &str, download: F) -> Result<DownloadedCover, RustyCovError>
+    where
+        F: FnOnce() -> Result<DownloadedCover, RustyCovError>,
+    {
+        let entry = Arc::clone(self.downloads.lock().unwrap().entry(url.to_string()).or_default());
+        let mut shared = entry.lock().unwrap();
+
+        let result = match &shared.cover {
+            Some(cover) => Ok(cover.clone()),
+            None => download(),
+        };
+
+        shared.pending = shared.pending.saturating_sub(1);
+        if shared.pending == 0 {
+            self.downloads.lock().unwrap().remove(url);
+        } else if shared.cover.is_none() &&
+            let Ok(cover) = &result
+        {
+            shared.cover = Some(cover.clone());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(path: &str, album_artist: Option<&str>, album: Option<&str>) -> CoverQuery {
+        CoverQuery {
+            path: PathBuf::from(path),
+            album_artist: album_artist.map(str::to_string),
+            album: album.map(str::to_string),
+            ..CoverQuery::default()
+        }
+    }
+
+    #[test]
+    fn album_key_ignores_case_and_directory_when_tagged() {
+        let first = AlbumKey::for_query(&query("/a/01.flac", Some("Artist"), Some("Album ")));
+        let second = AlbumKey::for_query(&query("/b/02.flac", Some("ARTIST"), Some("album")));
+        assert_eq!(first, second);
+        assert_eq!(first, AlbumKey::Tags { album_artist: "artist".to_string(), album: "album".to_string() });
+    }
+
+    #[test]
+    fn album_key_falls_back_to_the_directory() {
+        let key = AlbumKey::for_query(&query("/a/01.flac", None, Some("Album")));
+        assert_eq!(key, AlbumKey::Dir { dir: PathBuf::from("/a"), album: Some("album".to_string()) });
+        assert_ne!(key, AlbumKey::for_query(&query("/b/01.flac", None, Some("Album"))));
+        assert_ne!(key, AlbumKey::for_query(&query("/a/02.flac", None, Some("Other"))));
+        assert_eq!(AlbumKey::for_query(&query("/a/01.flac", Some("Artist"), None)), AlbumKey::Dir { dir: PathBuf::from("/a"), album: None });
+    }
+}
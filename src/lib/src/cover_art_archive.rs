@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use ureq::get;
+
+use crate::deps_download::DownloadError;
+use crate::error::RustyCovError;
+use crate::observer::RunObserver;
+use crate::source::{CoverLookup, CoverQuery, CoverSource};
+use crate::structs::{CoverInfo, Picked, ReleaseInfo};
+
+/// Default Cover Art Archive server.
+pub const DEFAULT_COVER_ART_ARCHIVE_URL: &str = "https://coverartarchive.org";
+
+/// Default MusicBrainz server, queried for the release info of a pick.
+pub const DEFAULT_MUSICBRAINZ_URL: &str = "https://musicbrainz.org";
+
+/// User agent sent to web services that ask clients to identify themselves, e.g. MusicBrainz.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"), " ( https://github.com/UnknownSuperficialNight/RustyCOV )");
+
+/// Size of the Cover Art Archive image used as the big cover of a pick.
+///
+/// The 1200 and 500 pixel thumbnails are missing for some older uploads; the big cover then falls
+/// back to the original image. The other sizes are kept as the small and original cover URLs so
+/// [`RunOptions::cover_url_order`](crate::options::RunOptions::cover_url_order) can still try them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverArtSize {
+    /// The 1200 pixel thumbnail.
+    #[default]
+    Large,
+    /// The 500 pixel thumbnail.
+    Medium,
+    /// The image as uploaded, which may be very large.
+    Original,
+}
+
+impl fmt::Display for CoverArtSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CoverArtSize::Large => "1200",
+            CoverArtSize::Medium => "500",
+            CoverArtSize::Original => "original",
+        })
+    }
+}
+
+impl FromStr for CoverArtSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "1200" | "large" => Ok(CoverArtSize::Large),
+            "500" | "medium" => Ok(CoverArtSize::Medium),
+            "original" => Ok(CoverArtSize::Original),
+            other => Err(format!("Unknown cover size {:?}, expected 1200, 500 or original", other)),
+        }
+    }
+}
+
+/// Cover source looking up the front cover of the MusicBrainz release tagged in each file.
+///
+/// Files without a `MUSICBRAINZ_ALBUMID` / `MusicBrainz Album Id` tag, or whose release has no
+/// front cover, are reported as not found without any search.
+#[derive(Debug, Clone)]
+pub struct CoverArtArchiveSource {
+    base_url: String,
+    musicbrainz_url: Option<String>,
+    size: CoverArtSize,
+}
+
+impl Default for CoverArtArchiveSource {
+    fn default() -> Self {
+        Self::new(DEFAULT_COVER_ART_ARCHIVE_URL)
+    }
+}
+
+impl CoverArtArchiveSource {
+    /// Creates a source querying the Cover Art Archive at `base_url`, e.g. a local mock server.
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            musicbrainz_url: Some(DEFAULT_MUSICBRAINZ_URL.to_string()),
+            size: CoverArtSize::default(),
+        }
+    }
+
+    /// Sets the MusicBrainz server queried for the title, artist and date of the release. `None`
+    /// leaves the release info empty apart from its URL.
+    pub fn musicbrainz_url(mut self, musicbrainz_url: Option<String>) -> Self {
+        self.musicbrainz_url = musicbrainz_url.map(|url| url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Sets the size used as the big cover.
+    pub fn size(mut self, size: CoverArtSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Fetches the release info from MusicBrainz. Failures are ignored since the cover is usable
+    /// without it.
+    fn release_info(&self, release_id: &str) -> Option<ReleaseInfo> {
+        let url = format!("{}/ws/2/release/{}?fmt=json&inc=artist-credits", self.musicbrainz_url.as_ref()?, release_id);
+        let release: MusicBrainzRelease = fetch_json(&url).ok().flatten()?;
+
+        let artist: String = release.artist_credit.iter().map(|credit| format!("{}{}", credit.name, credit.joinphrase)).collect();
+        let tracks: u32 = release.media.iter().filter_map(|medium| medium.track_count).sum();
+        Some(ReleaseInfo {
+            title: release.title,
+            artist: (!artist.is_empty()).then_some(artist),
+            date: release.date.filter(|date| !date.is_empty()),
+            tracks: (tracks > 0).then_some(tracks),
+            ..ReleaseInfo::default()
+        })
+    }
+}
+
+impl CoverSource for CoverArtArchiveSource {
+    fn find_cover(&self, query: &CoverQuery, _observer: &dyn RunObserver) -> Result<CoverLookup, RustyCovError> {
+        let Some(release_id) = query.musicbrainz_release_id.as_deref().map(str::trim).filter(|id| !id.is_empty()) else {
+            return Ok(CoverLookup::NotFound);
+        };
+
+        let url = format!("{}/release/{}", self.base_url, release_id);
+        let listing: Option<CoverArtListing> =
+            fetch_json(&url).map_err(|source| RustyCovError::Download { url: url.clone(), path: Some(query.path.clone()), source })?;
+        let Some(front) = listing.and_then(|listing| listing.images.into_iter().find(CoverArtImage::is_front)) else {
+            return Ok(CoverLookup::NotFound);
+        };
+
+        let thumbnail = |keys: &[&str]| keys.iter().find_map(|key| front.thumbnails.get(*key).cloned());
+        let large = thumbnail(&["1200"]);
+        let medium = thumbnail(&["500", "large"]);
+        let small = thumbnail(&["250", "small"]);
+        let big_cover_url = match self.size {
+            CoverArtSize::Large => large.unwrap_or_else(|| front.image.clone()),
+            CoverArtSize::Medium => medium.clone().unwrap_or_else(|| front.image.clone()),
+            CoverArtSize::Original => front.image.clone(),
+        };
+
+        let mut release_info = self.release_info(release_id).unwrap_or_default();
+        let musicbrainz_url = self.musicbrainz_url.as_deref().unwrap_or(DEFAULT_MUSICBRAINZ_URL);
+        release_info.url = Some(format!("{}/release/{}", musicbrainz_url, release_id));
+
+        Ok(CoverLookup::Picked(Box::new(Picked {
+            source: Some("coverartarchive".to_string()),
+            small_cover_url: match self.size {
+                CoverArtSize::Medium => small,
+                CoverArtSize::Large | CoverArtSize::Original => medium.or(small),
+            },
+            original_cover_url: Some(front.image),
+            release_info,
+            cover_info: CoverInfo { format: url_extension(&big_cover_url), ..CoverInfo::default() },
+            big_cover_url,
+            extra: Map::new(),
+        })))
+    }
+}
+
+/// Returns the image extension of `url`, e.g. `jpg` for `https://…/front-1200.jpg`.
+fn url_extension(url: &str) -> Option<String> {
+    let extension = Path::new(url).extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some("jpg".to_string()),
+        "png" | "gif" | "webp" => Some(extension),
+        _ => None,
+    }
+}
+
+/// Fetches and parses the JSON document at `url`.
+///
+/// Returns `Ok(None)` when the server answers 404 Not Found.
+fn fetch_json<T: DeserializeOwned>(url: &str) -> Result<Option<T>, DownloadError> {
+    let response = match get(url).header("User-Agent", USER_AGENT).header("Accept", "application/json").call() {
+        Ok(response) => response,
+        Err(ureq::Error::StatusCode(404)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let body = response.into_body().read_to_string()?;
+    Ok(Some(serde_json::from_str(&body)?))
+}
+
+/// Image listing of a release, see <https://musicbrainz.org/doc/Cover_Art_Archive/API>.
+#[derive(Deserialize)]
+struct CoverArtListing {
+    #[serde(default)]
+    images: Vec<CoverArtImage>,
+}
+
+#[derive(Deserialize)]
+struct CoverArtImage {
+    image: String,
+    #[serde(default)]
+    front: bool,
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    thumbnails: HashMap<String, String>,
+}
+
+impl CoverArtImage {
+    fn is_front(&self) -> bool {
+        self.front || self.types.iter().any(|kind| kind == "Front")
+    }
+}
+
+#[derive(Deserialize)]
+struct MusicBrainzRelease {
+    title: Option<String>,
+    date: Option<String>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Deserialize)]
+struct Medium {
+    #[serde(rename = "track-count")]
+    track_count: Option<u32>,
+}
@@ -0,0 +1,288 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::error::RustyCovError;
+use crate::name_pattern::{builtin_patterns, clean_name, strip_track_number};
+use crate::observer::RunObserver;
+use crate::query::QuerySettings;
+use crate::source::CoverQuery;
+use crate::structs::Picked;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// How often a running covit is checked for cancellation and its pick timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long covit may keep running after printing its pick before it is killed.
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+/// How covit is launched and how long it may wait for the user.
+#[derive(Debug, Clone)]
+pub struct CovitConfig {
+    /// Path of the covit binary.
+    pub covit_path: String,
+    /// Address of the COV website for launch.
+    pub address: String,
+    /// How long the user has to pick a cover before covit is killed. `None` waits forever.
+    pub pick_timeout: Option<Duration>,
+    /// Token that kills the running covit when tripped.
+    pub cancellation: CancellationToken,
+}
+
+impl CovitConfig {
+    pub fn new<S: Into<String>, A: Into<String>>(covit_path: S, address: A) -> Self {
+        Self {
+            covit_path: covit_path.into(),
+            address: address.into(),
+            pick_timeout: None,
+            cancellation: CancellationToken::default(),
+        }
+    }
+}
+
+/// How a single covit invocation ended.
+#[derive(Debug, Clone)]
+pub enum CovitOutcome {
+    /// The user picked a cover.
+    Picked(Box<Picked>),
+    /// covit exited without a pick, e.g. because nothing matched the query.
+    NotPicked,
+    /// covit printed nothing but `Listening: <address>`, which means the user closed the tab.
+    UserClosed,
+    /// No cover was picked within [`CovitConfig::pick_timeout`].
+    TimedOut,
+    /// The [cancellation token](CovitConfig::cancellation) was tripped.
+    Cancelled,
+}
+
+/// Run covit and return the picked file.
+///
+/// covit is first run on the file itself. If nothing is picked, it is run again with an explicit
+/// query: first the album and album artist (or artist) from the tags in `query`, then the artist
+/// and title parsed from the file name. Closing the tab, running out of time or cancelling stops
+/// the remaining queries.
+///
+/// Returns `Ok(None)` when no cover was picked, [`RustyCovError::Covit`] when the covit process
+/// could not be run and [`RustyCovError::CovitCrashed`] when it failed.
+///
+/// # Arguments
+///
+/// * `config` - covit binary, address, pick timeout and cancellation token.
+/// * `query` - Audio file to find a cover for, along with its tags.
+/// * `settings` - Sources and country to search, see [`QueryConfig::settings_for`](crate::query::QueryConfig::settings_for).
+/// * `observer` - Receives [`RunObserver::covit_listening`], [`RunObserver::user_closed_tab`] and
+///   [`RunObserver::covit_timed_out`].
+pub fn run_covit(
+    config: &CovitConfig,
+    query: &CoverQuery,
+    settings: &QuerySettings,
+    observer: &dyn RunObserver,
+) -> Result<Option<Picked>, RustyCovError> {
+    let input = &query.path;
+
+    // First attempt: run covit normally, then fall back to --query-artist and --query-album
+    let mut attempts = vec![None];
+    attempts.extend(fallback_queries(query, settings).into_iter().map(Some));
+
+    for attempt in attempts {
+        let mut cmd = Command::new(&config.covit_path);
+        cmd.arg("--address").arg(&config.address);
+
+        match attempt {
+            None => {
+                cmd.arg("--input").arg(input);
+            }
+            Some((title, artist_opt)) => {
+                cmd.arg("--query-album").arg(title);
+                if let Some(artist) = artist_opt &&
+                    !artist.is_empty()
+                {
+                    cmd.arg("--query-artist").arg(artist);
+                }
+            }
+        }
+
+        cmd.arg("--remote-agent")
+            .arg(format!("{} - {}", PROGRAM_NAME, VERSION))
+            .arg("--query-sources")
+            .arg(settings.sources_arg())
+            .arg("--query-country")
+            .arg(settings.country.as_str());
+
+        match run_covit_command(cmd, config, input, observer)? {
+            CovitOutcome::Picked(picked) => return Ok(Some(*picked)),
+            CovitOutcome::NotPicked => {}
+            CovitOutcome::UserClosed => {
+                observer.user_closed_tab(input);
+                return Ok(None);
+            }
+            CovitOutcome::TimedOut => {
+                observer.covit_timed_out(input);
+                return Ok(None);
+            }
+            CovitOutcome::Cancelled => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+/// Spawns `cmd` and follows its output line by line until it exits, times out or is cancelled.
+fn run_covit_command(
+    mut cmd: Command,
+    config: &CovitConfig,
+    input: &Path,
+    observer: &dyn RunObserver,
+) -> Result<CovitOutcome, RustyCovError> {
+    let covit_error = |source| RustyCovError::Covit { path: input.to_path_buf(), source };
+
+    let mut child = cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(covit_error)?;
+    let stdout = child.stdout.take().expect("covit stdout is piped");
+    let mut stderr = child.stderr.take().expect("covit stderr is piped");
+
+    let stderr_reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+    let (line_tx, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if line_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = config.pick_timeout.map(|timeout| Instant::now() + timeout);
+    let mut picked = None;
+    let mut other_output = false;
+
+    while picked.is_none() {
+        if config.cancellation.is_cancelled() {
+            kill(&mut child);
+            return Ok(CovitOutcome::Cancelled);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            kill(&mut child);
+            return Ok(CovitOutcome::TimedOut);
+        }
+
+        let line = match lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        if let Some(address) = line.strip_prefix("Listening:") {
+            observer.covit_listening(input, address.trim());
+        } else if let Some(json) = line.strip_prefix("Picked: ") {
+            match Picked::parse(json) {
+                Ok(parsed) => picked = Some(parsed),
+                Err(source) => {
+                    kill(&mut child);
+                    return Err(RustyCovError::InvalidPick { path: input.to_path_buf(), source });
+                }
+            }
+        } else if !line.trim().is_empty() {
+            other_output = true;
+        }
+    }
+
+    if let Some(picked) = picked {
+        wait_or_kill(&mut child, EXIT_GRACE);
+        return Ok(CovitOutcome::Picked(Box::new(picked)));
+    }
+
+    let status = child.wait().map_err(covit_error)?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    // covit shares the terminal's process group, so Ctrl+C reaches it as well as the handler
+    if config.cancellation.is_cancelled() {
+        return Ok(CovitOutcome::Cancelled);
+    }
+    if !status.success() {
+        return Err(RustyCovError::CovitCrashed { path: input.to_path_buf(), status, stderr: stderr.trim().to_string() });
+    }
+    if !other_output && stderr.trim().is_empty() {
+        return Ok(CovitOutcome::UserClosed);
+    }
+    Ok(CovitOutcome::NotPicked)
+}
+
+/// Kills covit and reaps it, ignoring a process that already exited.
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Gives covit `grace` to exit on its own before killing it.
+fn wait_or_kill(child: &mut Child, grace: Duration) {
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if !matches!(child.try_wait(), Ok(None)) {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    kill(child);
+}
+
+/// Builds the `(album, artist)` pairs covit is queried with after a plain run found nothing:
+///
+/// 1. The album tag with the album artist (or artist).
+/// 2. The fields of the first name pattern matching the path, trying the user's patterns before
+///    the [built-in ones](crate::name_pattern::BUILTIN_PATTERNS).
+/// 3. The artist and title split from the file name, once its track number and bracketed tags
+///    are stripped.
+///
+/// Duplicates and queries without an album are dropped.
+fn fallback_queries(query: &CoverQuery, settings: &QuerySettings) -> Vec<(String, Option<String>)> {
+    let mut queries: Vec<(String, Option<String>)> = Vec::new();
+
+    if let Some(album) = &query.album {
+        queries.push((album.clone(), query.album_artist.clone().or_else(|| query.artist.clone())));
+    }
+
+    let parsed = settings.name_patterns.iter().chain(&builtin_patterns()).find_map(|pattern| pattern.parse_path(&query.path));
+    if let Some(parsed) = parsed &&
+        let Some(album) = parsed.album.or(parsed.title)
+    {
+        queries.push((album, parsed.album_artist.or(parsed.artist)));
+    }
+
+    if let Some(file_stem) = &query.file_name {
+        let (artist_opt, title_opt) = parse_file_name(&clean_name(strip_track_number(file_stem)));
+        if let Some(title) = title_opt.filter(|t| !t.is_empty()) {
+            queries.push((title, artist_opt));
+        }
+    }
+
+    let mut seen = Vec::new();
+    queries.retain(|query| {
+        let new = !seen.contains(query);
+        seen.push(query.clone());
+        new
+    });
+    queries
+}
+
+/// Parses file name to extract artist and title.
+fn parse_file_name(file_stem: &str) -> (Option<String>, Option<String>) {
+    let delimiters = [" - ", " – ", " — ", " _ ", ":", " | "];
+
+    for delim in &delimiters {
+        if let Some(idx) = file_stem.find(delim) {
+            let (left, right) = file_stem.split_at(idx);
+            let right = &right[delim.len()..];
+            return (Some(left.trim().to_string()), Some(right.trim().to_string()));
+        }
+    }
+    (None, Some(file_stem.trim().to_string()))
+}
@@ -1,6 +1,5 @@
 #[cfg(all(unix, feature = "depend-on-ffmpeg"))]
 use std::io;
-#[cfg(all(unix, feature = "depend-on-ffmpeg"))]
 use std::path::Path;
 
 use thiserror::Error;
@@ -9,9 +8,11 @@ use xz2::stream::Error as XzError;
 #[cfg(all(windows, feature = "depend-on-ffmpeg"))]
 use zip::result::ZipError;
 
+use crate::error::RustyCovError;
 #[cfg(unix)]
 use crate::helpers::set_executable_permissions;
 use crate::helpers::{DownloadTarget, download_with_progress, get_current_dir, is_in_path};
+use crate::observer::RunObserver;
 
 #[derive(Debug, Clone)]
 pub struct DependencyPaths {
@@ -42,8 +43,12 @@ pub enum DownloadError {
     Request(#[from] ureq::Error),
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Invalid JSON response: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("Downloaded data is empty")]
     EmptyDownload,
+    #[error("Image is {width}x{height}, smaller than the minimum of {min}x{min}")]
+    TooSmall { width: u32, height: u32, min: u32 },
 }
 
 #[cfg(all(unix, feature = "depend-on-ffmpeg"))]
@@ -64,10 +69,25 @@ pub enum ExtractError {
     Zip(#[from] ZipError),
 }
 
-pub fn download_and_extract_deps() -> Result<DependencyPaths, Box<dyn std::error::Error>> {
+/// Locates the external binaries needed at runtime, downloading them next to the executable
+/// when they are neither present in `deps_bin` nor on the `PATH`.
+///
+/// # Arguments
+///
+/// * `observer` - Receives dependency and download progress events.
+/// * `covit_override` - covit binary to use instead of the downloaded one, e.g. a fake covit in
+///   tests. Returns [`RustyCovError::DependencyMissing`] if it does not exist.
+/// * `ffmpeg_override` - Directory holding the ffmpeg and ffprobe binaries to use instead of the
+///   downloaded ones. Returns [`RustyCovError::DependencyMissing`] if either does not exist.
+///   Ignored without the `depend-on-ffmpeg` feature.
+pub fn download_and_extract_deps(
+    observer: &dyn RunObserver,
+    covit_override: Option<&Path>,
+    ffmpeg_override: Option<&Path>,
+) -> Result<DependencyPaths, RustyCovError> {
     let exe_dir = get_current_dir();
     let bin_dir = exe_dir.join("deps_bin");
-    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&bin_dir).map_err(|source| RustyCovError::Io { path: bin_dir.clone(), source })?;
 
     // --- Platform/feature-specific constants ---
     #[cfg(all(unix, feature = "depend-on-ffmpeg"))]
@@ -95,9 +115,17 @@ pub fn download_and_extract_deps() -> Result<DependencyPaths, Box<dyn std::error
     #[cfg(windows)]
     const COVIT_BIN: &str = "covit.exe";
 
-    // --- Download and extract ffmpeg/ffprobe if needed ---
+    // --- Download and extract ffmpeg/ffprobe unless overridden ---
     #[cfg(feature = "depend-on-ffmpeg")]
-    let (ffmpeg_path, ffprobe_path) = {
+    let (ffmpeg_path, ffprobe_path) = if let Some(ffmpeg_dir) = ffmpeg_override {
+        let [ffmpeg, ffprobe] = FFMPEG_FILES.map(|bin| ffmpeg_dir.join(bin));
+        for (bin, path) in FFMPEG_FILES.iter().zip([&ffmpeg, &ffprobe]) {
+            if !path.exists() {
+                return Err(RustyCovError::DependencyMissing { name: bin.to_string(), path: path.clone() });
+            }
+        }
+        (ffmpeg.to_string_lossy().to_string(), ffprobe.to_string_lossy().to_string())
+    } else {
         let archive_path = bin_dir.join(FFMPEG_ARCHIVE);
         let mut extracted = [None, None];
 
@@ -113,19 +141,30 @@ pub fn download_and_extract_deps() -> Result<DependencyPaths, Box<dyn std::error
         }
 
         if need_download {
-            println!("Downloading ffmpeg archive...");
+            observer.dependency_downloading("ffmpeg archive");
             download_with_progress(
                 FFMPEG_URL,
                 DownloadTarget::File(archive_path.to_str().unwrap()),
-            )?;
+                observer,
+            )
+            .map_err(|source| RustyCovError::Download {
+                url: FFMPEG_URL.to_string(),
+                path: Some(archive_path.clone()),
+                source,
+            })?;
 
-            println!("Extracting ffmpeg/ffprobe...");
-            extract_selected_files(&archive_path, &FFMPEG_FILES, &bin_dir)?;
+            observer.dependency_extracting("ffmpeg/ffprobe");
+            extract_selected_files(&archive_path, &FFMPEG_FILES, &bin_dir)
+                .map_err(|source| RustyCovError::Extract { path: archive_path.clone(), source })?;
+            if let Err(source) = std::fs::remove_file(&archive_path) {
+                observer.error(&RustyCovError::Io { path: archive_path.clone(), source });
+            }
 
             #[cfg(unix)]
             for bin in &FFMPEG_FILES {
                 let out_path = bin_dir.join(bin);
-                set_executable_permissions(&out_path)?;
+                set_executable_permissions(&out_path)
+                    .map_err(|source| RustyCovError::Io { path: out_path.clone(), source })?;
             }
         }
 
@@ -133,7 +172,7 @@ pub fn download_and_extract_deps() -> Result<DependencyPaths, Box<dyn std::error
         for (i, bin) in FFMPEG_FILES.iter().enumerate() {
             let out_path = bin_dir.join(bin);
             if !out_path.exists() && !is_in_path(bin) {
-                return Err(format!("Failed to extract or find {}", bin).into());
+                return Err(RustyCovError::DependencyMissing { name: bin.to_string(), path: out_path });
             }
             extracted[i] = Some(out_path.to_string_lossy().to_string());
         }
@@ -141,17 +180,32 @@ pub fn download_and_extract_deps() -> Result<DependencyPaths, Box<dyn std::error
         (extracted[0].clone().unwrap(), extracted[1].clone().unwrap())
     };
 
-    // --- Always download covit ---
-    let covit_out_path = bin_dir.join(COVIT_BIN);
-    if !covit_out_path.exists() && !is_in_path(COVIT_BIN) {
-        println!("Downloading covit...");
-        download_with_progress(COVIT_URL, DownloadTarget::File(covit_out_path.to_str().unwrap()))?;
-        #[cfg(unix)]
-        set_executable_permissions(&covit_out_path)?;
-    }
+    // --- Download covit unless overridden ---
+    let covit = match covit_override {
+        Some(covit_path) => {
+            if !covit_path.exists() {
+                return Err(RustyCovError::DependencyMissing { name: "covit".to_string(), path: covit_path.to_path_buf() });
+            }
+            covit_path.to_string_lossy().to_string()
+        }
+        None => {
+            let covit_out_path = bin_dir.join(COVIT_BIN);
+            if !covit_out_path.exists() && !is_in_path(COVIT_BIN) {
+                observer.dependency_downloading("covit");
+                download_with_progress(COVIT_URL, DownloadTarget::File(covit_out_path.to_str().unwrap()), observer).map_err(|source| {
+                    RustyCovError::Download { url: COVIT_URL.to_string(), path: Some(covit_out_path.clone()), source }
+                })?;
+                #[cfg(unix)]
+                set_executable_permissions(&covit_out_path)
+                    .map_err(|source| RustyCovError::Io { path: covit_out_path.clone(), source })?;
+            }
+            covit_out_path.to_string_lossy().to_string()
+        }
+    };
 
     // --- Build DependencyPaths ---
-    let covit = covit_out_path.to_string_lossy().to_string();
+    #[cfg(not(feature = "depend-on-ffmpeg"))]
+    let _ = ffmpeg_override;
     #[cfg(feature = "depend-on-ffmpeg")]
     {
         Ok(DependencyPaths { ffmpeg: ffmpeg_path, ffprobe: ffprobe_path, covit })
@@ -175,7 +229,7 @@ fn extract_selected_files(
     files_to_extract: &[&str],
     output_dir: &Path,
 ) -> Result<(), ExtractError> {
-    use std::fs::{self, File};
+    use std::fs::File;
     let file = File::open(archive_path)?;
     let decompressor = xz2::read::XzDecoder::new(file);
     let mut archive = tar::Archive::new(decompressor);
@@ -192,9 +246,6 @@ fn extract_selected_files(
             set_executable_permissions(&out_path)?;
         }
     }
-    if let Err(err) = fs::remove_file(archive_path) {
-        eprintln!("Error deleting file: {err}");
-    }
     Ok(())
 }
 
@@ -228,8 +279,5 @@ fn extract_selected_files(
             std::io::copy(&mut entry, &mut out_file)?;
         }
     }
-    if let Err(err) = fs::remove_file(archive_path) {
-        eprintln!("Error deleting file: {}", err);
-    }
     Ok(())
 }
@@ -0,0 +1,155 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use lofty::error::LoftyError;
+use thiserror::Error;
+
+#[cfg(feature = "depend-on-ffmpeg")]
+use crate::deps_download::ExtractError;
+use crate::deps_download::DownloadError;
+use crate::structs::PickError;
+
+/// Boxed error used for sources whose concrete type depends on enabled features.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors returned by the public library API.
+///
+/// Every variant carries the path (or URL) that caused the failure so callers can report it or
+/// decide whether to retry, see [`RustyCovError::is_retryable`].
+#[derive(Error, Debug)]
+pub enum RustyCovError {
+    #[error("Failed to read tags from {path:?}: {source}")]
+    TagRead {
+        path: PathBuf,
+        #[source]
+        source: LoftyError,
+    },
+    #[error("Failed to write tags to {path:?}: {source}")]
+    TagWrite {
+        path: PathBuf,
+        #[source]
+        source: LoftyError,
+    },
+    #[error("Failed to decode image{}: {source}", display_path(.path))]
+    ImageDecode {
+        path: Option<PathBuf>,
+        #[source]
+        source: BoxError,
+    },
+    #[error("Failed to encode image{}: {source}", display_path(.path))]
+    ImageEncode {
+        path: Option<PathBuf>,
+        #[source]
+        source: BoxError,
+    },
+    #[error("Failed to run covit for {path:?}: {source}")]
+    Covit {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("covit failed for {path:?} with {status}{}", display_stderr(.stderr))]
+    CovitCrashed { path: PathBuf, status: ExitStatus, stderr: String },
+    #[error("covit returned an invalid pick for {path:?}: {source}")]
+    InvalidPick {
+        path: PathBuf,
+        #[source]
+        source: PickError,
+    },
+    #[error("Failed to find dependency {name} at {path:?}")]
+    DependencyMissing { name: String, path: PathBuf },
+    #[error("Failed to download {url}{}: {source}", display_path(.path))]
+    Download {
+        url: String,
+        path: Option<PathBuf>,
+        #[source]
+        source: DownloadError,
+    },
+    #[cfg(feature = "depend-on-ffmpeg")]
+    #[error("Failed to extract {path:?}: {source}")]
+    Extract {
+        path: PathBuf,
+        #[source]
+        source: ExtractError,
+    },
+    #[error("{path:?} changed after the backup session, not restoring it")]
+    BackupMismatch { path: PathBuf },
+    #[error("The skip policy only applies in per-file mode, not in album folder or extract mode")]
+    SkipPolicyNotPerFile,
+    #[error("Automatic picking needs a cover source that does not ask the user, such as the Cover Art Archive")]
+    AutoPickNeedsAutomaticSource,
+    #[error("JPEG quality {quality} is out of range, expected 1-100")]
+    InvalidJpegQuality { quality: u8 },
+    #[error("Input path {path:?} does not exist")]
+    InputNotFound { path: PathBuf },
+    #[error("Job {job} panicked: {message}")]
+    JobPanicked { job: usize, message: String },
+    #[error("I/O error on {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl RustyCovError {
+    /// Returns the path associated with the error, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            RustyCovError::TagRead { path, .. } |
+            RustyCovError::TagWrite { path, .. } |
+            RustyCovError::Covit { path, .. } |
+            RustyCovError::CovitCrashed { path, .. } |
+            RustyCovError::InvalidPick { path, .. } |
+            RustyCovError::DependencyMissing { path, .. } |
+            RustyCovError::BackupMismatch { path } |
+            RustyCovError::InputNotFound { path } |
+            RustyCovError::Io { path, .. } => Some(path),
+            #[cfg(feature = "depend-on-ffmpeg")]
+            RustyCovError::Extract { path, .. } => Some(path),
+            RustyCovError::ImageDecode { path, .. } |
+            RustyCovError::ImageEncode { path, .. } |
+            RustyCovError::Download { path, .. } => path.as_deref(),
+            RustyCovError::SkipPolicyNotPerFile |
+            RustyCovError::AutoPickNeedsAutomaticSource |
+            RustyCovError::InvalidJpegQuality { .. } |
+            RustyCovError::JobPanicked { .. } => None,
+        }
+    }
+
+    /// Whether retrying the same operation may succeed, e.g. after a network failure.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, RustyCovError::Download { .. })
+    }
+
+    pub(crate) fn image_decode<E: Into<BoxError>>(source: E) -> Self {
+        RustyCovError::ImageDecode { path: None, source: source.into() }
+    }
+
+    #[cfg_attr(not(any(feature = "jpeg-opt", feature = "png-opt")), expect(dead_code))]
+    pub(crate) fn image_encode<E: Into<BoxError>>(source: E) -> Self {
+        RustyCovError::ImageEncode { path: None, source: source.into() }
+    }
+
+    /// Attaches `file_path` to image errors that were raised without knowing the file.
+    pub(crate) fn with_path<P: AsRef<Path>>(mut self, file_path: P) -> Self {
+        if let RustyCovError::ImageDecode { path, .. } | RustyCovError::ImageEncode { path, .. } = &mut self &&
+            path.is_none()
+        {
+            *path = Some(file_path.as_ref().to_path_buf());
+        }
+        self
+    }
+}
+
+fn display_path(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!(" for {:?}", path),
+        None => String::new(),
+    }
+}
+
+fn display_stderr(stderr: &str) -> String {
+    if stderr.is_empty() { String::new() } else { format!(": {}", stderr) }
+}
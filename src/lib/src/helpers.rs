@@ -1,12 +1,12 @@
 use std::env;
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
-use indicatif::{ProgressBar, ProgressStyle};
 use ureq::get;
 
 use crate::deps_download::DownloadError;
+use crate::observer::RunObserver;
 
 /// Checks if a command is in the user's PATH (environmental variable).
 ///
@@ -94,9 +94,11 @@ pub enum DownloadTarget<'a> {
 ///
 /// * `url` - URL of the file to download.
 /// * `target` - The target where the downloaded file will be saved. If `Memory`, returns the bytes.
+/// * `observer` - Receives download progress events.
 pub fn download_with_progress(
     url: &str,
     target: DownloadTarget,
+    observer: &dyn RunObserver,
 ) -> Result<Option<Vec<u8>>, DownloadError> {
     let (headers, body) = get(url).call()?.into_parts();
 
@@ -125,26 +127,8 @@ pub fn download_with_progress(
         }
     }
 
-    // Setup progress bar
-    let pb = if let Some(total) = total_size {
-        let pb = ProgressBar::new(total);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {binary_bytes_per_sec} {bar:40} {binary_bytes} / {binary_total_bytes}")
-                .expect("Failed to create ProgressStyle object")
-                .progress_chars("#-"),
-        );
-        pb
-    } else {
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {spinner} Received {binary_bytes}")
-                .expect("Failed to create ProgressStyle object")
-                .progress_chars("#-"),
-        );
-        pb
-    };
+    observer.download_started(url, total_size);
+    let mut downloaded = 0u64;
 
     // Read loop
     loop {
@@ -156,13 +140,14 @@ pub fn download_with_progress(
                 } else if let Some(mem) = memory_buffer.as_mut() {
                     mem.extend_from_slice(&buffer[..n]);
                 }
-                pb.inc(n as u64);
+                downloaded += n as u64;
+                observer.download_progress(url, downloaded, total_size);
             }
             Err(e) => return Err(e.into()),
         }
     }
 
-    pb.finish();
+    observer.download_finished(url);
 
     match memory_buffer {
         Some(bytes) => Ok(Some(bytes)),
@@ -170,6 +155,88 @@ pub fn download_with_progress(
     }
 }
 
+/// Extensions a folder cover may be written with, by album folder or extract mode.
+const SIDECAR_EXTENSIONS: [&str; 6] = ["jpg", "png", "gif", "webp", "bmp", "tiff"];
+
+/// Returns the existing album folder cover (`<album_name>.jpg`, `<album_name>.png`, ...) in `dir`.
+pub fn find_sidecar(dir: &Path, album_name: &str) -> Option<PathBuf> {
+    SIDECAR_EXTENSIONS.iter().map(|ext| dir.join(format!("{}.{}", album_name, ext))).find(|path| path.exists())
+}
+
+/// Returns the file extension matching the image format of `bytes`, detected from its magic
+/// number.
+pub fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.starts_with(b"GIF8") {
+        Some("gif")
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Reads the width and height of a JPEG, PNG or GIF image from its header without decoding it.
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32);
+    let be32 = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+    match image_extension(bytes)? {
+        // IHDR is always the first chunk
+        "png" => Some((be32(16)?, be32(20)?)),
+        "gif" => {
+            let le16 = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u32);
+            Some((le16(6)?, le16(8)?))
+        }
+        "jpg" => {
+            // Walk the segments until a start-of-frame marker, which holds the dimensions
+            let mut at = 2;
+            while *bytes.get(at)? == 0xFF {
+                let marker = *bytes.get(at + 1)?;
+                if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                    return Some((be16(at + 7)?, be16(at + 5)?));
+                }
+                at += 2 + be16(at + 2)? as usize;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of `bytes` as a hex string.
+///
+/// The hash is stable across builds and platforms, so it can be stored on disk to identify file
+/// and picture contents.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a(FNV_OFFSET_BASIS, bytes))
+}
+
+/// Returns the [`content_hash`] of everything `reader` yields, reading it in chunks so that large
+/// audio files are never held in memory.
+pub fn content_hash_of_reader<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => hash = fnv1a(hash, &buffer[..read]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
 /// Extracts the first contiguous digit substring from `s`
 /// Returns Some((number_value, digit_length)) or None if no digits found.
 pub fn extract_first_number(s: &str) -> Option<(usize, usize)> {
@@ -193,3 +260,32 @@ pub fn extract_first_number(s: &str) -> Option<(usize, usize)> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_image_dimensions_from_headers() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&600u32.to_be_bytes());
+        png.extend_from_slice(&400u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((600, 400)));
+
+        let gif = b"GIF89a\x58\x02\x90\x01";
+        assert_eq!(image_dimensions(gif), Some((600, 400)));
+
+        // APP0 segment, then a baseline start-of-frame with the height before the width
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0x90, 0x02, 0x58]);
+        assert_eq!(image_dimensions(&jpeg), Some((600, 400)));
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_images() {
+        assert_eq!(image_dimensions(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0"), None);
+        assert_eq!(image_dimensions(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]), None);
+        assert_eq!(image_dimensions(b"RIFF\0\0\0\0WEBPVP8 "), None);
+        assert_eq!(image_dimensions(b""), None);
+    }
+}
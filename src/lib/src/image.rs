@@ -6,6 +6,9 @@ use image::ImageReader;
 #[cfg(feature = "jpeg-opt")]
 use lofty::picture::Picture;
 
+#[cfg(any(feature = "jpeg-opt", feature = "png-opt"))]
+use crate::error::RustyCovError;
+
 /// Converts a PNG image to JPEG format.
 ///
 /// This function reads a PNG image from the provided cursor, converts it to JPEG,
@@ -24,18 +27,22 @@ pub(crate) fn convert_png_to_jpeg(
     cursor: &mut std::io::Cursor<Vec<u8>>,
     picture: &mut Picture,
     jpeg_optimise: Option<u8>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), RustyCovError> {
     use image::ImageReader;
 
     cursor.set_position(0);
 
     // Decode PNG from memory
-    let img = ImageReader::new(&mut *cursor).with_guessed_format()?.decode()?;
+    let img = ImageReader::new(&mut *cursor)
+        .with_guessed_format()
+        .map_err(RustyCovError::image_decode)?
+        .decode()
+        .map_err(RustyCovError::image_decode)?;
 
     // Encode PNG image as JPEG with recommended quality (80) into a new Vec<u8>
     // We just convert here, optimisation will be done in optimise_jpeg
     let mut jpeg_bytes = Vec::new();
-    img.write_to(&mut Cursor::new(&mut jpeg_bytes), image::ImageFormat::Jpeg)?;
+    img.write_to(&mut Cursor::new(&mut jpeg_bytes), image::ImageFormat::Jpeg).map_err(RustyCovError::image_encode)?;
 
     // Replace the original buffer with the JPEG data
     *cursor.get_mut() = jpeg_bytes;
@@ -46,7 +53,7 @@ pub(crate) fn convert_png_to_jpeg(
         optimise_jpeg(cursor, jpeg_quality)?;
     }
 
-    *picture = Picture::from_reader(&mut *cursor)?;
+    *picture = Picture::from_reader(&mut *cursor).map_err(RustyCovError::image_decode)?;
 
     Ok(())
 }
@@ -61,19 +68,23 @@ pub(crate) fn convert_png_to_jpeg(
 /// * `cursor` - A mutable cursor containing the JPEG image data.
 /// * `quality` - The quality of the output JPEG image (1-100).
 #[cfg(feature = "jpeg-opt")]
-pub(crate) fn optimise_jpeg(cursor: &mut std::io::Cursor<Vec<u8>>, quality: u8) -> Result<(), Box<dyn std::error::Error>> {
+pub(crate) fn optimise_jpeg(cursor: &mut std::io::Cursor<Vec<u8>>, quality: u8) -> Result<(), RustyCovError> {
     use image::codecs::jpeg::JpegEncoder;
 
     cursor.set_position(0);
 
     // Decode JPEG from memory
-    let img = ImageReader::new(&mut *cursor).with_guessed_format()?.decode()?;
+    let img = ImageReader::new(&mut *cursor)
+        .with_guessed_format()
+        .map_err(RustyCovError::image_decode)?
+        .decode()
+        .map_err(RustyCovError::image_decode)?;
 
     // Encode as JPEG with specified quality into a new Vec<u8>
     let mut jpeg_bytes = Vec::new();
     {
         let mut encoder = JpegEncoder::new_with_quality(&mut jpeg_bytes, quality);
-        encoder.encode_image(&img)?;
+        encoder.encode_image(&img).map_err(RustyCovError::image_encode)?;
     }
 
     // Replace the original buffer
@@ -92,7 +103,7 @@ pub(crate) fn optimise_jpeg(cursor: &mut std::io::Cursor<Vec<u8>>, quality: u8)
 ///
 /// * `cursor` - A mutable cursor containing the PNG image data.
 #[cfg(feature = "png-opt")]
-pub(crate) fn optimise_png(cursor: &mut std::io::Cursor<Vec<u8>>) -> Result<(), Box<dyn std::error::Error>> {
+pub(crate) fn optimise_png(cursor: &mut std::io::Cursor<Vec<u8>>) -> Result<(), RustyCovError> {
     use oxipng::{Options as OxipngOptions, StripChunks, optimize_from_memory};
 
     // Get the PNG data from the cursor
@@ -104,7 +115,7 @@ pub(crate) fn optimise_png(cursor: &mut std::io::Cursor<Vec<u8>>) -> Result<(),
     options.optimize_alpha = true;
 
     // Optimise the PNG data in memory
-    let optimised_data = optimize_from_memory(data, &options)?;
+    let optimised_data = optimize_from_memory(data, &options).map_err(RustyCovError::image_encode)?;
 
     // Replace the cursor's buffer with the optimised data
     *cursor.get_mut() = optimised_data;
@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::RustyCovError;
+use crate::structs::Picked;
+
+/// A single line of the progress journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// A cover was picked for `target`, a directory in album folder mode and a file otherwise.
+    Picked { target: PathBuf, picked: Box<Picked> },
+    /// A file was embedded into or stripped.
+    FileDone { path: PathBuf },
+    /// Every file of an album folder was processed and its cover file written.
+    DirDone { dir: PathBuf },
+}
+
+/// On-disk progress journal allowing an interrupted run to be resumed.
+///
+/// The journal is a JSON Lines file that is appended to and flushed after every entry, so a
+/// crash loses at most the entry being written. On resume, completed directories and files are
+/// skipped and recorded picks are reused without asking covit again.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+    done_dirs: HashSet<PathBuf>,
+    done_files: HashSet<PathBuf>,
+    picks: HashMap<PathBuf, Picked>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the journal file, created if missing.
+    /// * `resume` - Load the existing entries and append to them; otherwise the file is truncated.
+    pub fn open(path: &Path, resume: bool) -> Result<Self, RustyCovError> {
+        let to_error = |source| RustyCovError::Io { path: path.to_path_buf(), source };
+
+        let mut done_dirs = HashSet::new();
+        let mut done_files = HashSet::new();
+        let mut picks = HashMap::new();
+
+        if resume && path.exists() {
+            let reader = BufReader::new(File::open(path).map_err(to_error)?);
+            for line in reader.lines() {
+                let line = line.map_err(to_error)?;
+                // A partially written last line is expected after a crash; skip it.
+                match serde_json::from_str::<JournalEntry>(&line) {
+                    Ok(JournalEntry::Picked { target, picked }) => {
+                        picks.insert(target, *picked);
+                    }
+                    Ok(JournalEntry::FileDone { path }) => {
+                        done_files.insert(path);
+                    }
+                    Ok(JournalEntry::DirDone { dir }) => {
+                        done_dirs.insert(dir);
+                    }
+                    Err(_) => continue,
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(resume).write(true).truncate(!resume).open(path).map_err(to_error)?;
+
+        Ok(Self { path: path.to_path_buf(), file: Mutex::new(file), done_dirs, done_files, picks })
+    }
+
+    /// Returns whether the album folder `dir` was completed by a previous run.
+    pub fn is_dir_done(&self, dir: &Path) -> bool {
+        self.done_dirs.contains(dir)
+    }
+
+    /// Returns whether `path` was embedded into or stripped by a previous run.
+    pub fn is_file_done(&self, path: &Path) -> bool {
+        self.done_files.contains(path)
+    }
+
+    /// Returns the cover picked for `target` by a previous run.
+    pub fn picked(&self, target: &Path) -> Option<&Picked> {
+        self.picks.get(target)
+    }
+
+    /// Appends `entry` to the journal and flushes it to disk.
+    pub fn record(&self, entry: &JournalEntry) -> Result<(), RustyCovError> {
+        let to_error = |source| RustyCovError::Io { path: self.path.clone(), source };
+
+        let mut line = serde_json::to_string(entry).map_err(|e| to_error(e.into()))?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes()).map_err(to_error)?;
+        file.sync_data().map_err(to_error)
+    }
+}
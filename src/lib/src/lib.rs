@@ -1,34 +1,58 @@
+pub mod backup;
+mod cache;
+pub mod cancel;
+pub mod covit;
+pub mod cover_art_archive;
 pub mod deps_download;
+pub mod error;
 pub mod helpers;
 #[doc(hidden)]
 pub mod image;
 
+pub mod journal;
 pub mod lofty;
+pub mod name_pattern;
+pub mod observer;
+pub mod options;
+pub mod plan;
+mod pool;
+pub mod query;
+pub mod rank;
+pub mod report;
+pub mod scan;
+pub mod source;
 pub mod structs;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread::{JoinHandle, spawn};
 
-use serde_json::Value;
-
-use crate::deps_download::download_and_extract_deps;
-use crate::helpers::{DownloadTarget, download_with_progress};
-use crate::lofty::{embed_cover_image, process_cover_image, remove_embedded_art_from_file};
-use crate::structs::{CoverInfo, Picked, ReleaseInfo, RustyCov};
-
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
-
-const QUERTY_SOURCE: &str =
-    "booth,amazonmusic,applemusic,musicbrainz,discogs,fanarttv,soundcloud,itunes,tidal";
-const QUERY_COUNTRY: &str = "gb";
+use crate::backup::BackupStore;
+use crate::cache::{AlbumKey, CoverCache};
+use crate::cancel::CancellationToken;
+use crate::deps_download::{DownloadError, download_and_extract_deps};
+use crate::error::RustyCovError;
+use crate::helpers::{DownloadTarget, download_with_progress, find_sidecar, image_dimensions, image_extension};
+use crate::journal::{Journal, JournalEntry};
+use crate::lofty::{
+    StripPolicy, TagTarget, best_embedded_front_cover, embed_cover_image, most_common_album_tags, picture_extension, process_cover_image,
+    remove_embedded_art_from_file,
+};
+use crate::observer::{RunObserver, RunSummary, TargetKind, TerminalObserver};
+use crate::options::RunOptions;
+use crate::plan::{Plan, PlanMode};
+use crate::pool::WorkerPool;
+use crate::rank::RankingRules;
+use crate::report::{ImageSizes, ReportCollector, RunReport};
+use crate::scan::{DEFAULT_LOW_RESOLUTION, DEFAULT_SIDECAR_NAMES, Scan};
+use crate::source::{CoverQuery, CoverSource, CovitSource};
+use crate::structs::{CoverUrl, CoverUrlKind, Picked, RustyCov};
 
 /// Runs the main logic of the application.
 ///
+/// This is a thin wrapper over [`run_with`] kept for callers using positional arguments. Progress
+/// is printed to the terminal.
+///
 /// # Arguments
 ///
 /// * `input_string` - Input directory or file to process.
@@ -48,410 +72,696 @@ pub fn run(
     jpeg_optimise: Option<u8>,
     png_opt: bool,
     album_folder_mode: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), RustyCovError> {
+    let options = RunOptions {
+        input: input_string.to_string(),
+        cov_address: cov_address.map(str::to_string),
+        convert_png_to_jpg,
+        jpeg_optimise,
+        png_opt,
+        album_folder_mode: album_folder_mode.map(str::to_string),
+        observer: Some(Arc::new(TerminalObserver::new())),
+        ..RunOptions::default()
+    };
+    run_with(&options).map(|_| ())
+}
+
+/// Runs the main logic of the application with the supplied [`RunOptions`].
+///
+/// Progress is reported to the observer configured with
+/// [`RunOptionsBuilder::observer`](crate::options::RunOptionsBuilder::observer); when none is set,
+/// the run is silent. The run stops early, after finishing the files being written and stripping
+/// the folders whose cover file is already written, once the
+/// [`CancellationToken`](crate::cancel::CancellationToken) passed to
+/// [`RunOptionsBuilder::cancellation_token`](crate::options::RunOptionsBuilder::cancellation_token)
+/// is cancelled. When [`RunOptions::dry_run`] is set, the run stops after building the
+/// [`Plan`] and reporting it to the observer, see [`plan`]; [`RunOptions::scan`] likewise stops
+/// after the [`Scan`], see [`scan`]. When [`RunOptions::extract`] is set,
+/// the cover files are written from the embedded art and covit is neither downloaded nor run.
+///
+/// # Arguments
+///
+/// * `options` - Options controlling the run, see [`RunOptions::builder`].
+///
+/// # Returns
+///
+/// A [`RunReport`] listing the outcome of every directory and file, or an error if the run could
+/// not start.
+pub fn run_with(options: &RunOptions) -> Result<RunReport, RustyCovError> {
+    let input_string = options.input.as_str();
+    let convert_png_to_jpg = options.convert_png_to_jpg;
+    let jpeg_optimise = options.jpeg_optimise;
+    let png_opt = options.png_opt;
+    let album_folder_mode = options.album_folder_mode.as_deref();
+    let observer = options.observer();
+    let cancellation = options.cancellation_token();
+
+    check_options(options).inspect_err(|e| observer.error(e))?;
+    if options.dry_run {
+        let plan = plan(options).inspect_err(|e| observer.error(e))?;
+        observer.plan_ready(&plan);
+        return Ok(RunReport::default());
+    }
+    if options.scan {
+        let scan = scan(options).inspect_err(|e| observer.error(e))?;
+        observer.scan_ready(&scan);
+        return Ok(RunReport::default());
+    }
+
     let mut rusty_cov_global = RustyCov::default();
 
     // Populate files from input
-    rusty_cov_global.populate_from_input(input_string);
-
-    if let Some(cov_address) = cov_address {
-        rusty_cov_global.cov_address = Some(cov_address);
+    if let Err(e) = rusty_cov_global.populate_from_input(input_string) {
+        observer.error(&e);
+        return Err(e);
     }
 
-    // Download dependencies
-    match download_and_extract_deps() {
-        Ok(deps) => {
-            rusty_cov_global.deps = Some(deps);
-        }
-        Err(e) => {
-            eprintln!("Failed to download dependencies: {e}");
-            return Err(e);
+    rusty_cov_global.cov_address = Some(options.cov_address());
+
+    // Download dependencies, covit is only needed when no other cover source is set and covers
+    // are not extracted from the files
+    let cover_source: Option<Arc<dyn CoverSource>> = if options.extract.is_some() {
+        None
+    } else {
+        match &options.cover_source {
+            Some(cover_source) => Some(Arc::clone(cover_source)),
+            None => match download_and_extract_deps(observer.as_ref(), options.covit_path.as_deref(), options.ffmpeg_dir.as_deref()) {
+                Ok(deps) => {
+                    let query = options.query.resolved(&input_root(input_string));
+                    if let Some(files_by_dir) = &rusty_cov_global.files {
+                        let files = files_by_dir.values().flatten().map(PathBuf::as_path);
+                        for query_override in query.unmatched_overrides(files) {
+                            observer.query_override_unused(&query_override.dir);
+                        }
+                    }
+                    let source = CovitSource::new(deps.covit(), options.cov_address(), query)
+                        .pick_timeout(options.pick_timeout)
+                        .cancellation_token(options.cancellation_token());
+                    rusty_cov_global.deps = Some(deps);
+                    Some(Arc::new(source))
+                }
+                Err(e) => {
+                    observer.error(&e);
+                    return Err(e);
+                }
+            },
         }
-    }
-
-    // Create atomic bools for features
-    let convert_png_to_jpg = Arc::new(AtomicBool::new(convert_png_to_jpg));
-    let png_opt = Arc::new(AtomicBool::new(png_opt));
+    };
 
     // If no files were found, exit.
-    if rusty_cov_global.files.is_none() {
-        eprintln!("No supported audio/video files were found exiting.");
-        return Ok(());
-    }
+    let Some(files_by_dir) = &rusty_cov_global.files else {
+        observer.no_files_found();
+        return Ok(RunReport::default());
+    };
+
+    // Every event passes through the collector to build the report
+    let collector = Arc::new(ReportCollector::new(observer, files_by_dir, album_folder_mode.or(options.extract.as_deref())));
+    let observer: Arc<dyn RunObserver> = collector.clone();
+
+    let journal = match &options.journal {
+        Some(path) => match Journal::open(path, options.resume) {
+            Ok(journal) => Some(Arc::new(journal)),
+            Err(e) => {
+                observer.error(&e);
+                return Err(e);
+            }
+        },
+        None => None,
+    };
+
+    let backup = match &options.backup_dir {
+        Some(root) => match BackupStore::create(root) {
+            Ok(store) => {
+                observer.backup_session_started(store.session_dir());
+                Some(Arc::new(store))
+            }
+            Err(e) => {
+                observer.error(&e);
+                return Err(e);
+            }
+        },
+        None => None,
+    };
 
-    let folders_edited = Arc::new(AtomicUsize::new(0));
-    let files_edited = Arc::new(AtomicUsize::new(0));
+    let ctx = JobContext {
+        observer: Arc::clone(&observer),
+        cancellation: cancellation.clone(),
+        journal: journal.clone(),
+        backup,
+        cache: Arc::new(CoverCache::default()),
+        cover_url_order: options.cover_url_order(),
+        min_cover_size: options.min_cover_size,
+        tag_target: options.tag_target.clone(),
+        strip_policy: options.strip_policy,
+        convert_png_to_jpg: Arc::new(AtomicBool::new(convert_png_to_jpg)),
+        jpeg_optimise,
+        png_opt: Arc::new(AtomicBool::new(png_opt)),
+        folders_edited: Arc::new(AtomicUsize::new(0)),
+        files_edited: Arc::new(AtomicUsize::new(0)),
+    };
 
     match &mut rusty_cov_global.files {
         Some(files_by_dir) if !files_by_dir.is_empty() => {
-            let handles: Arc<Mutex<HashMap<usize, JoinHandle<()>>>> =
-                Arc::new(Mutex::new(HashMap::new()));
-            let mut job_id = 0usize;
+            let mut pool: WorkerPool<()> = WorkerPool::new(options.jobs());
+            let mut files_skipped = 0;
 
             for (dir, files) in files_by_dir.iter_mut() {
+                if cancellation.is_cancelled() {
+                    break;
+                }
+                observer.folder_started(dir);
+
+                if let Some(cover_name) = &options.extract {
+                    if journal.as_ref().is_some_and(|journal| journal.is_dir_done(dir)) {
+                        observer.already_done(dir);
+                    } else if find_sidecar(dir, cover_name).is_some() {
+                        observer.folder_skipped(dir);
+                    } else {
+                        let files: Vec<PathBuf> = std::mem::take(files);
+                        let (dir, cover_name, strip) = (dir.clone(), cover_name.clone(), options.strip_extracted);
+                        let ctx = ctx.clone();
+
+                        pool.submit(move || ctx.extract_job(&dir, &cover_name, files, strip));
+                    }
+                    continue;
+                }
+                // Only missing when extracting
+                let Some(cover_source) = cover_source.as_deref() else {
+                    continue;
+                };
+
                 if let Some(album_name) = album_folder_mode {
-                    // Check if art already exists (either .jpg or .png)
-                    let jpg_path = dir.join(format!("{}.jpg", album_name));
-                    let png_path = dir.join(format!("{}.png", album_name));
-                    if jpg_path.exists() || png_path.exists() {
-                        println!("Album art already exists in {:?}, skipping.", dir);
+                    if journal.as_ref().is_some_and(|journal| journal.is_dir_done(dir)) {
+                        observer.already_done(dir);
                         continue;
                     }
 
-                    // Try each file in the folder until run_covit succeeds
-                    let mut picked_opt = None;
-                    for file in &mut *files {
-                        if let Some(picked) = run_covit(
-                            rusty_cov_global.deps.as_ref().unwrap().covit.as_str(),
-                            rusty_cov_global.cov_address.unwrap(),
-                            file,
-                        ) {
-                            picked_opt = Some(picked);
-                            break;
+                    // A pick recorded by an interrupted run is reused even if its cover file was
+                    // already written, so the remaining files still get stripped.
+                    let mut picked_opt = journal.as_ref().and_then(|journal| journal.picked(dir)).cloned();
+
+                    // Check if art already exists (in any format)
+                    if picked_opt.is_none() && find_sidecar(dir, album_name).is_some() {
+                        observer.folder_skipped(dir);
+                        continue;
+                    }
+
+                    // Try each file in the folder until the source finds a cover, querying with
+                    // the tags most of the folder agrees on
+                    let mut deferred = false;
+                    if picked_opt.is_none() {
+                        let album_tags = most_common_album_tags(files);
+                        for file in &mut *files {
+                            if cancellation.is_cancelled() {
+                                break;
+                            }
+                            let query = CoverQuery::new(file, album_tags.clone());
+                            match find_cover(cover_source, &query, dir, options.auto_pick.as_ref(), observer.as_ref()) {
+                                Ok(CoverChoice::Picked(picked)) => {
+                                    ctx.record(JournalEntry::Picked { target: dir.clone(), picked: picked.clone() });
+                                    picked_opt = Some(*picked);
+                                    break;
+                                }
+                                // The other files are queried with the same tags, so they would be
+                                // deferred too
+                                Ok(CoverChoice::Deferred) => {
+                                    deferred = true;
+                                    break;
+                                }
+                                Ok(CoverChoice::NotFound) => {}
+                                Err(e) => observer.error(&e),
+                            }
                         }
                     }
 
                     if let Some(picked) = picked_opt {
-                        println!(
-                            "Folder: {:?}\nArtist: {}\nTitle: {}\nDate: {}\nCover Type: {}\nImage Size: {} bytes\nDimensions: {}x{}\nBig Cover URL: {}\n",
-                            dir,
-                            picked.release_info.artist,
-                            picked.release_info.title,
-                            picked.release_info.date,
-                            picked.cover_info.format,
-                            picked.cover_info.size,
-                            picked.cover_info.width,
-                            picked.cover_info.height,
-                            picked.big_cover_url
-                        );
-
-                        // Drain files here to transfer ownership to the thread
-                        let drained_files: Vec<PathBuf> = std::mem::take(files);
+                        observer.cover_picked(dir, TargetKind::Folder, &picked);
 
-                        // Clone variables needed inside the thread
+                        // Drain files here to transfer ownership to the job
+                        let drained_files: Vec<PathBuf> = std::mem::take(files);
                         let album_name = album_name.to_string();
-                        let convert_png_to_jpg = Arc::clone(&convert_png_to_jpg);
-                        let png_opt = Arc::clone(&png_opt);
                         let dir = dir.clone();
-                        let folders_edited = Arc::clone(&folders_edited);
-                        let files_edited = Arc::clone(&files_edited);
-
-                        let handle = spawn(move || {
-                            // Download the image
-                            let image_bytes = match download_with_progress(
-                                &picked.big_cover_url,
-                                DownloadTarget::Memory,
-                            ) {
-                                Ok(Some(bytes)) => bytes,
-                                Ok(None) => {
-                                    eprintln!("Unexpected None");
-                                    return;
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to download image: {}", e);
-                                    return;
-                                }
-                            };
-
-                            let (processed_bytes, _) = match process_cover_image(
-                                image_bytes,
-                                &convert_png_to_jpg,
-                                jpeg_optimise,
-                                &png_opt,
-                            ) {
-                                Ok(res) => res,
-                                Err(e) => {
-                                    eprintln!("Failed to process cover image: {}", e);
-                                    return;
-                                }
-                            };
+                        let ctx = ctx.clone();
 
-                            let art_path =
-                                dir.join(format!("{}.{}", album_name, picked.cover_info.format));
-                            if let Err(e) = std::fs::write(&art_path, &processed_bytes) {
-                                eprintln!("Failed to save album art to {:?}: {}", art_path, e);
-                                return;
-                            }
-                            println!("Saved album art to {:?}", art_path);
-
-                            // Remove embedded art from all files in this folder
-                            #[cfg(feature = "parallel")]
-                            {
-                                use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-
-                                drained_files.par_iter().for_each(|file| {
-                                    if let Err(e) = remove_embedded_art_from_file(file) {
-                                        eprintln!(
-                                            "Failed to remove embedded art from {:?}: {}",
-                                            file, e
-                                        );
-                                    } else {
-                                        println!("Removed embedded art from {:?}", file);
-                                        files_edited.fetch_add(1, Ordering::SeqCst);
-                                    }
-                                });
-                            }
-                            #[cfg(not(feature = "parallel"))]
-                            {
-                                for file in drained_files {
-                                    if let Err(e) = remove_embedded_art_from_file(&file) {
-                                        eprintln!(
-                                            "Failed to remove embedded art from {:?}: {}",
-                                            file, e
-                                        );
-                                    } else {
-                                        println!("Removed embedded art from {:?}", file);
-                                        files_edited.fetch_add(1, Ordering::SeqCst);
-                                    }
-                                }
-                            }
-                            folders_edited.fetch_add(1, Ordering::SeqCst);
-                        });
-
-                        let mut handles_lock = handles.lock().unwrap();
-                        handles_lock.insert(job_id, handle);
-                        job_id += 1;
-                    } else {
-                        println!("No cover info found for folder {:?}", dir);
+                        pool.submit(move || ctx.album_job(&dir, &album_name, &picked, drained_files));
+                    } else if !deferred {
+                        observer.no_cover_found(dir, TargetKind::Folder);
                     }
                 } else {
                     for path in files.drain(..) {
-                        if let Some(picked) = run_covit(
-                            rusty_cov_global.deps.as_ref().unwrap().covit.as_str(),
-                            rusty_cov_global.cov_address.unwrap(),
-                            &path,
-                        ) {
-                            println!(
-                                "Artist: {}\nTitle: {}\nDate: {}\nCover Type: {}\nImage Size: {} bytes\nDimensions: {}x{}\nBig Cover URL: {}\n",
-                                picked.release_info.artist,
-                                picked.release_info.title,
-                                picked.release_info.date,
-                                picked.cover_info.format,
-                                picked.cover_info.size,
-                                picked.cover_info.width,
-                                picked.cover_info.height,
-                                picked.big_cover_url
-                            );
-
-                            let convert_png_to_jpg = Arc::clone(&convert_png_to_jpg);
-                            let png_opt = Arc::clone(&png_opt);
-                            let files_edited = Arc::clone(&files_edited);
-
-                            let handle = spawn(move || {
-                                // Download the image using ureq
-                                let image_bytes = match download_with_progress(
-                                    &picked.big_cover_url,
-                                    DownloadTarget::Memory,
-                                ) {
-                                    Ok(Some(bytes)) => bytes,
-                                    Ok(None) => {
-                                        eprintln!("Unexpected None");
-                                        return;
+                        if cancellation.is_cancelled() {
+                            break;
+                        }
+                        if journal.as_ref().is_some_and(|journal| journal.is_file_done(&path)) {
+                            observer.already_done(&path);
+                            continue;
+                        }
+                        // Unreadable files are processed as usual and fail when embedding
+                        if options.skip_existing.is_some_and(|policy| policy.is_met_by(&path).unwrap_or(false)) {
+                            observer.existing_art_kept(&path);
+                            files_skipped += 1;
+                            continue;
+                        }
+
+                        let query = CoverQuery::for_file(&path);
+                        // Other tracks of an album reuse its pick unless asked not to
+                        let album_key = (!options.ask_per_file).then(|| AlbumKey::for_query(&query));
+
+                        let picked = match journal.as_ref().and_then(|journal| journal.picked(&path)) {
+                            Some(picked) => Some(picked.clone()),
+                            None => match album_key.as_ref().and_then(|key| ctx.cache.picked(key)) {
+                                Some(picked) => {
+                                    ctx.record(JournalEntry::Picked { target: path.clone(), picked: Box::new(picked.clone()) });
+                                    Some(picked)
+                                }
+                                None => match find_cover(cover_source, &query, &path, options.auto_pick.as_ref(), observer.as_ref()) {
+                                    Ok(CoverChoice::Picked(picked)) => {
+                                        ctx.record(JournalEntry::Picked { target: path.clone(), picked: picked.clone() });
+                                        if let Some(key) = album_key {
+                                            ctx.cache.insert_pick(key, (*picked).clone());
+                                        }
+                                        Some(*picked)
                                     }
+                                    Ok(CoverChoice::Deferred) => continue,
+                                    Ok(CoverChoice::NotFound) => None,
                                     Err(e) => {
-                                        eprintln!("Failed to download image: {}", e);
-                                        return;
+                                        observer.error(&e);
+                                        continue;
                                     }
-                                };
-
-                                if let Err(e) = embed_cover_image(
-                                    path,
-                                    image_bytes,
-                                    convert_png_to_jpg,
-                                    jpeg_optimise,
-                                    png_opt,
-                                ) {
-                                    eprintln!("Failed to embed cover: {}", e);
-                                } else {
-                                    files_edited.fetch_add(1, Ordering::SeqCst);
-                                }
-                            });
-                            let mut handles_lock = handles.lock().unwrap();
-                            handles_lock.insert(job_id, handle);
-                            job_id += 1;
+                                },
+                            },
+                        };
+
+                        if let Some(picked) = picked {
+                            observer.cover_picked(&path, TargetKind::File, &picked);
+
+                            ctx.cache.reserve(&picked.big_cover_url);
+                            let ctx = ctx.clone();
+                            pool.submit(move || ctx.file_job(&path, &picked));
                         } else {
-                            println!("No cover info found for {:?}", path);
+                            observer.no_cover_found(&path, TargetKind::File);
                         }
                     }
                 }
             }
 
-            // Wait for all threads to finish
-            let mut handles_lock = handles.lock().unwrap();
-            for (job_id, handle) in handles_lock.drain() {
-                match handle.join() {
-                    Ok(_) => {}
-                    Err(panic) => eprintln!("Job {} panicked: {:?}", job_id, panic),
+            // Wait for all jobs to finish
+            for (job, result) in pool.join().into_iter().enumerate() {
+                if let Err(panic) = result {
+                    let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                        (Some(message), _) => message.to_string(),
+                        (None, Some(message)) => message.clone(),
+                        (None, None) => "unknown panic payload".to_string(),
+                    };
+                    observer.error(&RustyCovError::JobPanicked { job, message });
                 }
             }
 
-            // Print running summary at the end
-            if album_folder_mode.is_some() {
-                println!(
-                    "\nTotal: {} folder(s) finished, {} file(s) removed metadata.",
-                    folders_edited.load(Ordering::SeqCst),
-                    files_edited.load(Ordering::SeqCst)
-                );
-            } else {
-                println!("\nTotal: {} file(s) finished.", files_edited.load(Ordering::SeqCst));
-            }
+            observer.run_finished(&RunSummary {
+                album_mode: album_folder_mode.is_some() || (options.extract.is_some() && options.strip_extracted),
+                folders_edited: ctx.folders_edited.load(Ordering::SeqCst),
+                files_edited: ctx.files_edited.load(Ordering::SeqCst),
+                files_skipped,
+                cancelled: cancellation.is_cancelled(),
+            });
         }
-        _ => eprintln!("No files were found or the input was invalid."),
+        _ => observer.no_files_found(),
     }
-    Ok(())
+    Ok(collector.report())
 }
 
-/// Run covit and return the picked file.
-pub fn run_covit(covit_path: &str, address: &str, input: &std::path::PathBuf) -> Option<Picked> {
-    use std::process::Command;
-
-    // First attempt: run covit normally
-    let output = Command::new(covit_path)
-        .arg("--address")
-        .arg(address)
-        .arg("--input")
-        .arg(input)
-        .arg("--remote-agent")
-        .arg(format!("{} - {}", PROGRAM_NAME, VERSION))
-        .arg("--query-sources")
-        .arg(QUERTY_SOURCE)
-        .arg("--query-country")
-        .arg(QUERY_COUNTRY)
-        .output()
-        .ok()?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
-
-    // Check if output only contains "Listening: <number>"
-    if stdout.lines().all(|line| line.starts_with("Listening:")) && stderr.trim().is_empty() {
-        println!("User closed the tab");
-        return None; // User closed the browser tab
+/// Cover image downloaded for a pick, along with the URL it came from.
+#[derive(Clone)]
+pub(crate) struct DownloadedCover {
+    bytes: Vec<u8>,
+    url: CoverUrl,
+}
+
+/// State shared by the main loop and the cover jobs running on the worker pool.
+#[derive(Clone)]
+struct JobContext {
+    observer: Arc<dyn RunObserver>,
+    cancellation: CancellationToken,
+    journal: Option<Arc<Journal>>,
+    backup: Option<Arc<BackupStore>>,
+    cache: Arc<CoverCache>,
+    cover_url_order: Vec<CoverUrlKind>,
+    min_cover_size: Option<u32>,
+    tag_target: TagTarget,
+    strip_policy: StripPolicy,
+    convert_png_to_jpg: Arc<AtomicBool>,
+    jpeg_optimise: Option<u8>,
+    png_opt: Arc<AtomicBool>,
+    folders_edited: Arc<AtomicUsize>,
+    files_edited: Arc<AtomicUsize>,
+}
+
+impl JobContext {
+    /// Appends `entry` to the journal, if one is kept.
+    fn record(&self, entry: JournalEntry) {
+        if let Some(journal) = &self.journal &&
+            let Err(e) = journal.record(&entry)
+        {
+            self.observer.error(&e);
+        }
     }
 
-    let picked = parse_covit_output(output.stdout);
+    /// Saves the pictures of `path` to the backup store, if one is kept. Returns `false` if the
+    /// backup failed, in which case the file must not be modified.
+    fn backup_file(&self, path: &Path) -> bool {
+        match &self.backup {
+            Some(backup) => match backup.backup_file(path) {
+                Ok(()) => true,
+                Err(e) => {
+                    self.observer.error(&e);
+                    false
+                }
+            },
+            None => true,
+        }
+    }
 
-    // If picked is not None, return it
-    if let Some(picked) = picked {
-        return Some(picked);
+    /// Records the contents of a file the run has just written in the backup session.
+    fn backup_modified(&self, path: &Path) {
+        if let Some(backup) = &self.backup &&
+            let Err(e) = backup.record_modified(path)
+        {
+            self.observer.error(&e);
+        }
     }
 
-    // Fallback: parse file name for artist and title
-    let file_stem = input.file_stem()?.to_str()?;
-    let (artist_opt, title_opt) = parse_file_name(file_stem);
+    /// Downloads the cover for an album folder, writes it as `<album_name>.<ext>` and removes the
+    /// embedded art from every file in the folder.
+    fn album_job(&self, dir: &Path, album_name: &str, picked: &Picked, files: Vec<PathBuf>) {
+        if self.cancellation.is_cancelled() {
+            return;
+        }
 
-    // Only retry if we have at least a title
-    let title = match title_opt {
-        Some(ref t) if !t.is_empty() => t,
-        _ => return None,
-    };
+        // Download the image
+        let image_bytes = match self.download_cover(picked, dir) {
+            Ok(cover) => {
+                self.observer.cover_downloaded(dir, &cover.url);
+                cover.bytes
+            }
+            Err(e) => {
+                self.observer.error(&e);
+                return;
+            }
+        };
+
+        let downloaded_bytes = image_bytes.len() as u64;
+        let (processed_bytes, _) =
+            match process_cover_image(image_bytes, &self.convert_png_to_jpg, self.jpeg_optimise, &self.png_opt) {
+                Ok(res) => res,
+                Err(e) => {
+                    self.observer.error(&e.with_path(dir));
+                    return;
+                }
+            };
 
-    // Second attempt: run covit with --query-artist and --query-album
-    let output = run_covit_query(covit_path, address, title, artist_opt)?;
-    parse_covit_output(output.stdout)
-}
+        if self.cancellation.is_cancelled() {
+            return;
+        }
+        // The bytes may have been converted, so the format reported by the store is only a fallback
+        let format = image_extension(&processed_bytes).or(picked.cover_info.format.as_deref()).unwrap_or("jpg");
+        let art_path = dir.join(format!("{}.{}", album_name, format));
+        let created = !art_path.exists();
+        if let Err(source) = std::fs::write(&art_path, &processed_bytes) {
+            self.observer.error(&RustyCovError::Io { path: art_path, source });
+            return;
+        }
+        if created &&
+            let Some(backup) = &self.backup &&
+            let Err(e) = backup.record_sidecar(&art_path)
+        {
+            self.observer.error(&e);
+        }
+        self.observer.sidecar_saved(&art_path, ImageSizes { before: downloaded_bytes, after: processed_bytes.len() as u64 });
 
-/// Parses covit output to extract Picked information.
-fn parse_covit_output(stdout: Vec<u8>) -> Option<Picked> {
-    let stdout = String::from_utf8_lossy(&stdout);
-
-    for line in stdout.lines() {
-        if let Some(json) = line.strip_prefix("Picked: ") {
-            // Attempt to parse into serde_json::Value first
-            if let Ok(value) = serde_json::from_str::<Value>(json) {
-                // Now we can extract fields and create a Picked instance
-                let picked = Picked {
-                    big_cover_url: value
-                        .get("bigCoverUrl")
-                        .and_then(Value::as_str)
-                        .unwrap_or("")
-                        .to_string(),
-                    release_info: ReleaseInfo {
-                        title: value
-                            .get("releaseInfo")
-                            .and_then(|v| v.get("title").and_then(Value::as_str))
-                            .unwrap_or("Unknown Title")
-                            .to_string(),
-                        artist: value
-                            .get("releaseInfo")
-                            .and_then(|v| v.get("artist").and_then(Value::as_str))
-                            .unwrap_or("Unknown Artist")
-                            .to_string(),
-                        date: value
-                            .get("releaseInfo")
-                            .and_then(|v| v.get("date").and_then(Value::as_str))
-                            .unwrap_or("Unknown Date")
-                            .to_string(),
-                        tracks: value
-                            .get("releaseInfo")
-                            .and_then(|v| v.get("tracks").and_then(Value::as_u64))
-                            .map(|v| v as u32),
-                    },
-                    cover_info: CoverInfo {
-                        format: value
-                            .get("coverInfo")
-                            .and_then(|v| v.get("format").and_then(Value::as_str))
-                            .unwrap_or("Unknown Format")
-                            .to_string(),
-                        height: value
-                            .get("coverInfo")
-                            .and_then(|v| v.get("height").and_then(Value::as_u64))
-                            .unwrap_or(0) as u32,
-                        width: value
-                            .get("coverInfo")
-                            .and_then(|v| v.get("width").and_then(Value::as_u64))
-                            .unwrap_or(0) as u32,
-                        size: value
-                            .get("coverInfo")
-                            .and_then(|v| v.get("size").and_then(Value::as_u64))
-                            .unwrap_or(0),
-                    },
-                };
+        // Remove embedded art from all files in this folder
+        self.strip_files(files);
+        self.finish_dir(dir);
+    }
+
+    /// Writes the best front cover embedded in the files of a folder as `<cover_name>.<ext>`, then
+    /// removes the embedded art from every file if `strip` is set. Nothing is downloaded.
+    fn extract_job(&self, dir: &Path, cover_name: &str, files: Vec<PathBuf>, strip: bool) {
+        if self.cancellation.is_cancelled() {
+            return;
+        }
+        let Some(picture) = best_embedded_front_cover(&files) else {
+            self.observer.no_cover_found(dir, TargetKind::Folder);
+            return;
+        };
+
+        let art_path = dir.join(format!("{}.{}", cover_name, picture_extension(&picture)));
+        let created = !art_path.exists();
+        if let Err(source) = std::fs::write(&art_path, picture.data()) {
+            self.observer.error(&RustyCovError::Io { path: art_path, source });
+            return;
+        }
+        if created &&
+            let Some(backup) = &self.backup &&
+            let Err(e) = backup.record_sidecar(&art_path)
+        {
+            self.observer.error(&e);
+        }
+        let size = picture.data().len() as u64;
+        self.observer.sidecar_saved(&art_path, ImageSizes { before: size, after: size });
+
+        if strip {
+            self.strip_files(files);
+        }
+        self.finish_dir(dir);
+    }
+
+    /// Removes the embedded art selected by the strip policy from `files`.
+    ///
+    /// Cancellation is not checked: once the cover file of a folder is written its files are all
+    /// stripped, otherwise a later run without a journal would skip the folder because of the cover
+    /// file and leave the remaining art in place.
+    fn strip_files(&self, files: Vec<PathBuf>) {
+        // Files stripped before an interrupted run stopped are not touched again
+        let files: Vec<PathBuf> = match &self.journal {
+            Some(journal) => files.into_iter().filter(|file| !journal.is_file_done(file)).collect(),
+            None => files,
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+            files.par_iter().for_each(|file| self.strip_file(file));
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            files.iter().for_each(|file| self.strip_file(file));
+        }
+    }
+
+    /// Records a folder whose cover file was written and whose files were stripped as done.
+    fn finish_dir(&self, dir: &Path) {
+        self.record(JournalEntry::DirDone { dir: dir.to_path_buf() });
+        self.folders_edited.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn strip_file(&self, file: &Path) {
+        if !self.backup_file(file) {
+            return;
+        }
+        match remove_embedded_art_from_file(file, self.strip_policy) {
+            Err(e) => self.observer.error(&e),
+            Ok(0) => {
+                self.record(JournalEntry::FileDone { path: file.to_path_buf() });
+                self.observer.art_unchanged(file);
+            }
+            Ok(_) => {
+                self.backup_modified(file);
+                self.record(JournalEntry::FileDone { path: file.to_path_buf() });
+                self.observer.art_stripped(file);
+                self.files_edited.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Downloads the cover of `picked` into memory for the folder or file at `target`.
+    ///
+    /// The cover URLs of the pick are tried in the configured order; a URL that fails or whose
+    /// image is smaller than the minimum cover size falls through to the next one. The error of
+    /// the last URL tried is returned if none works.
+    fn download_cover(&self, picked: &Picked, target: &Path) -> Result<DownloadedCover, RustyCovError> {
+        let mut last_error = None;
+        for url in picked.cover_urls(&self.cover_url_order) {
+            if self.cancellation.is_cancelled() {
+                break;
+            }
+            let to_error = |source| RustyCovError::Download { url: url.url.clone(), path: Some(target.to_path_buf()), source };
 
-                return Some(picked);
+            let bytes = match download_with_progress(&url.url, DownloadTarget::Memory, self.observer.as_ref()) {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => {
+                    last_error = Some(to_error(DownloadError::EmptyDownload));
+                    continue;
+                }
+                Err(e) => {
+                    last_error = Some(to_error(e));
+                    continue;
+                }
+            };
+
+            // Images whose size cannot be read from the header are accepted
+            if let Some(min) = self.min_cover_size &&
+                let Some((width, height)) = image_dimensions(&bytes) &&
+                (width < min || height < min)
+            {
+                last_error = Some(to_error(DownloadError::TooSmall { width, height, min }));
+                continue;
             }
+
+            return Ok(DownloadedCover { bytes, url });
         }
+
+        Err(last_error.unwrap_or_else(|| RustyCovError::Download {
+            url: picked.big_cover_url.clone(),
+            path: Some(target.to_path_buf()),
+            source: DownloadError::EmptyDownload,
+        }))
     }
 
-    None
+    /// Downloads the cover for a single file and embeds it. Tracks sharing a cover download it
+    /// once.
+    fn file_job(&self, path: &Path, picked: &Picked) {
+        if self.cancellation.is_cancelled() {
+            return;
+        }
+
+        // Download the image, or reuse it if another track with the same cover already did
+        let image_bytes = match self.cache.fetch(&picked.big_cover_url, || self.download_cover(picked, path)) {
+            Ok(cover) => {
+                self.observer.cover_downloaded(path, &cover.url);
+                cover.bytes
+            }
+            Err(e) => {
+                self.observer.error(&e);
+                return;
+            }
+        };
+
+        if self.cancellation.is_cancelled() || !self.backup_file(path) {
+            return;
+        }
+
+        let downloaded_bytes = image_bytes.len() as u64;
+        let embedded =
+            embed_cover_image(path, image_bytes, &self.tag_target, Arc::clone(&self.convert_png_to_jpg), self.jpeg_optimise, Arc::clone(&self.png_opt));
+        match embedded {
+            Ok(embedded) => {
+                self.backup_modified(path);
+                self.record(JournalEntry::FileDone { path: path.to_path_buf() });
+                self.observer.tags_updated(path, &embedded.tags);
+                self.observer.file_embedded(path, ImageSizes { before: downloaded_bytes, after: embedded.image_bytes });
+                self.files_edited.fetch_add(1, Ordering::SeqCst);
+            }
+            Err(e) => self.observer.error(&e),
+        }
+    }
 }
 
-/// Runs covit with --query-artist and --query-album.
-fn run_covit_query(
-    covit_path: &str,
-    address: &str,
-    title: &str,
-    artist_opt: Option<String>,
-) -> Option<std::process::Output> {
-    let mut cmd = Command::new(covit_path);
-    cmd.arg("--address").arg(address).arg("--query-album").arg(title);
-
-    if let Some(ref artist) = artist_opt &&
-        !artist.is_empty()
+/// Works out every change a run with `options` would make without touching any file.
+///
+/// For each directory this records whether a cover file already exists (album folder mode), which
+/// files would be embedded, stripped or skipped, and how many picture bytes each file currently
+/// carries. covit is not invoked and dependencies are not downloaded.
+pub fn plan(options: &RunOptions) -> Result<Plan, RustyCovError> {
+    check_options(options)?;
+    let mut rusty_cov_global = RustyCov::default();
+    rusty_cov_global.populate_from_input(options.input.as_str())?;
+
+    let mode = match (options.album_folder_mode.as_deref(), options.extract.as_deref()) {
+        (Some(cover_name), _) => PlanMode::AlbumFolder { cover_name },
+        (None, Some(cover_name)) => PlanMode::Extract { cover_name, strip: options.strip_extracted },
+        (None, None) => PlanMode::PerFile { skip_existing: options.skip_existing },
+    };
+    Ok(match &rusty_cov_global.files {
+        Some(files_by_dir) => Plan::from_files(files_by_dir, mode),
+        None => Plan { album_mode: mode.writes_cover_file(), directories: Vec::new() },
+    })
+}
+
+/// Reports the artwork of every directory of the input without invoking covit or writing any
+/// file.
+///
+/// The cover file looked for is the one named by [`RunOptions::album_folder_mode`] or
+/// [`RunOptions::extract`], or any of [`DEFAULT_SIDECAR_NAMES`] otherwise. Art smaller than
+/// [`RunOptions::min_cover_size`], or [`DEFAULT_LOW_RESOLUTION`] pixels if unset, is reported as
+/// low resolution.
+pub fn scan(options: &RunOptions) -> Result<Scan, RustyCovError> {
+    let mut rusty_cov_global = RustyCov::default();
+    rusty_cov_global.populate_from_input(options.input.as_str())?;
+
+    let sidecar_names: Vec<&str> = match options.album_folder_mode.as_deref().or(options.extract.as_deref()) {
+        Some(cover_name) => vec![cover_name],
+        None => DEFAULT_SIDECAR_NAMES.to_vec(),
+    };
+    Ok(match &rusty_cov_global.files {
+        Some(files_by_dir) => Scan::from_files(files_by_dir, &sidecar_names, options.min_cover_size.unwrap_or(DEFAULT_LOW_RESOLUTION)),
+        None => Scan::default(),
+    })
+}
+
+/// Answer of [`find_cover`].
+enum CoverChoice {
+    Picked(Box<Picked>),
+    /// Automatic picking found only candidates below the confidence threshold.
+    Deferred,
+    NotFound,
+}
+
+/// Rejects invalid options and options that would otherwise be silently ignored.
+fn check_options(options: &RunOptions) -> Result<(), RustyCovError> {
+    if options.skip_existing.is_some() && (options.album_folder_mode.is_some() || options.extract.is_some()) {
+        return Err(RustyCovError::SkipPolicyNotPerFile);
+    }
+    if let Some(quality) = options.jpeg_optimise &&
+        !(1..=100).contains(&quality)
     {
-        cmd.arg("--query-artist").arg(artist);
+        return Err(RustyCovError::InvalidJpegQuality { quality });
     }
+    // Without a cover source the run asks the user in covit
+    if options.auto_pick.is_some() && options.cover_source.as_ref().is_none_or(|source| source.is_interactive()) {
+        return Err(RustyCovError::AutoPickNeedsAutomaticSource);
+    }
+    Ok(())
+}
 
-    cmd.arg("--remote-agent")
-        .arg(format!("{} - {}", PROGRAM_NAME, VERSION))
-        .arg("--query-sources")
-        .arg(QUERTY_SOURCE)
-        .arg("--query-country")
-        .arg(QUERY_COUNTRY);
-
-    cmd.output().ok()
+/// Returns the directory relative query override directories are taken from: the input itself,
+/// or the folder of an input file.
+fn input_root(input: &str) -> PathBuf {
+    let input = Path::new(input);
+    if input.is_file() { input.parent().map(Path::to_path_buf).unwrap_or_default() } else { input.to_path_buf() }
 }
 
-/// Parses file name to extract artist and title.
-fn parse_file_name(file_stem: &str) -> (Option<String>, Option<String>) {
-    let delimiters = [" - ", " – ", " — ", " _ ", ":", " | "];
+/// Asks `source` for a cover for `target`. Without `ranking`, the source's pick (or first
+/// candidate) is used; with it, the best scoring candidate is used if it reaches the threshold.
+fn find_cover(
+    source: &dyn CoverSource,
+    query: &CoverQuery,
+    target: &Path,
+    ranking: Option<&RankingRules>,
+    observer: &dyn RunObserver,
+) -> Result<CoverChoice, RustyCovError> {
+    let lookup = source.find_cover(query, observer)?;
+    let Some(ranking) = ranking else {
+        return Ok(lookup.into_picked().map_or(CoverChoice::NotFound, |picked| CoverChoice::Picked(Box::new(picked))));
+    };
 
-    for delim in &delimiters {
-        if let Some(idx) = file_stem.find(delim) {
-            let (left, right) = file_stem.split_at(idx);
-            let right = &right[delim.len()..];
-            return (Some(left.trim().to_string()), Some(right.trim().to_string()));
-        }
+    let Some(best) = ranking.rank(query, lookup.into_candidates()).into_iter().next() else {
+        return Ok(CoverChoice::NotFound);
+    };
+    if best.score < ranking.threshold {
+        observer.cover_deferred(target, best.score);
+        return Ok(CoverChoice::Deferred);
     }
-    (None, Some(file_stem.trim().to_string()))
+    observer.cover_auto_picked(target, best.score);
+    Ok(CoverChoice::Picked(Box::new(best.picked)))
 }
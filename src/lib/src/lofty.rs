@@ -1,13 +1,20 @@
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use lofty::config::{GlobalOptions, WriteOptions, apply_global_options};
-use lofty::picture::{Picture, PictureType};
+use lofty::file::{FileType, TaggedFile};
+use lofty::picture::{MimeType, Picture, PictureType};
 use lofty::prelude::*;
 use lofty::probe::Probe;
-use lofty::tag::Tag;
+use lofty::tag::{Tag, TagType};
+use serde::{Deserialize, Serialize};
 
+use crate::error::RustyCovError;
+use crate::helpers::{image_dimensions, image_extension};
 #[cfg(feature = "png-opt")]
 use crate::image::optimise_png;
 #[cfg(feature = "jpeg-opt")]
@@ -15,60 +22,406 @@ use crate::image::{convert_png_to_jpeg, optimise_jpeg};
 
 const ALLOCATION_LIMIT: usize = 1024 * 1024 * 1024;
 
+/// Which tags of an audio file a cover is embedded into.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TagTarget {
+    /// The primary tag, or the first tag the file has, creating the primary tag if it has none.
+    #[default]
+    Primary,
+    /// Every existing tag that supports pictures, creating the primary tag if none does.
+    AllPictureTags,
+    /// The listed tag types, created if missing. Types that cannot hold pictures or that the file
+    /// format does not support are skipped, and the primary tag is used if none is left.
+    Types(Vec<TagType>),
+}
+
+impl fmt::Display for TagTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagTarget::Primary => f.write_str("primary"),
+            TagTarget::AllPictureTags => f.write_str("all"),
+            TagTarget::Types(types) => {
+                let names: Vec<&str> = types.iter().map(|tag_type| tag_type_name(*tag_type)).collect();
+                f.write_str(&names.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for TagTarget {
+    type Err = String;
+
+    /// Parses `primary`, `all` or a comma separated list of [tag type names](tag_type_name).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "primary" => Ok(TagTarget::Primary),
+            "all" => Ok(TagTarget::AllPictureTags),
+            list => list
+                .split(',')
+                .map(|name| tag_type_from_name(name.trim()).ok_or_else(|| format!("Unknown tag type {:?}", name.trim())))
+                .collect::<Result<Vec<_>, _>>()
+                .map(TagTarget::Types),
+        }
+    }
+}
+
+impl TryFrom<String> for TagTarget {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TagTarget> for String {
+    fn from(target: TagTarget) -> Self {
+        target.to_string()
+    }
+}
+
+/// Which embedded pictures are removed from the files of a folder in album folder mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum StripPolicy {
+    /// Front covers only, leaving back covers, booklet scans, artist photos and disc art alone.
+    #[default]
+    FrontCover,
+    /// Every picture except booklet pages and back covers.
+    KeepBookletAndBack,
+    /// Every picture.
+    All,
+}
+
+impl StripPolicy {
+    /// Returns whether pictures of type `picture_type` in a file of `file_type` are removed under
+    /// this policy.
+    pub fn strips(self, file_type: FileType, picture_type: PictureType) -> bool {
+        match self {
+            StripPolicy::FrontCover => is_front_cover(file_type, picture_type),
+            StripPolicy::KeepBookletAndBack => !matches!(picture_type, PictureType::Leaflet | PictureType::CoverBack),
+            StripPolicy::All => true,
+        }
+    }
+}
+
+impl fmt::Display for StripPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StripPolicy::FrontCover => "front",
+            StripPolicy::KeepBookletAndBack => "keep-booklet",
+            StripPolicy::All => "all",
+        })
+    }
+}
+
+impl FromStr for StripPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "front" => Ok(StripPolicy::FrontCover),
+            "keep-booklet" => Ok(StripPolicy::KeepBookletAndBack),
+            "all" => Ok(StripPolicy::All),
+            other => Err(format!("Unknown strip policy {:?}, expected front, keep-booklet or all", other)),
+        }
+    }
+}
+
+impl TryFrom<String> for StripPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<StripPolicy> for String {
+    fn from(policy: StripPolicy) -> Self {
+        policy.to_string()
+    }
+}
+
+/// Provenance marker appended to the description of every embedded cover, e.g.
+/// `RustyCOV 1792108800`, recording when it was embedded in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Provenance {
+    pub embedded_at: u64,
+}
+
+impl Provenance {
+    const PREFIX: &'static str = "RustyCOV ";
+
+    /// Returns the marker for a cover embedded now.
+    pub fn now() -> Self {
+        Self { embedded_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() }
+    }
+
+    /// Returns the marker at the end of a picture description, if any.
+    pub fn from_description(description: &str) -> Option<Self> {
+        let start = description.rfind(Self::PREFIX)?;
+        description[start + Self::PREFIX.len()..].trim().parse().ok().map(|embedded_at| Self { embedded_at })
+    }
+
+    /// Returns `description` with this marker appended, replacing any marker it already ends
+    /// with so that the rest of the text is kept.
+    pub fn describe(&self, description: Option<&str>) -> String {
+        let text = description.unwrap_or_default();
+        let text = match Self::from_description(text) {
+            Some(_) => &text[..text.rfind(Self::PREFIX).unwrap_or_default()],
+            None => text,
+        };
+        match text.trim() {
+            "" => self.to_string(),
+            text => format!("{} {}", text, self),
+        }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", Self::PREFIX, self.embedded_at)
+    }
+}
+
+impl FromStr for Provenance {
+    type Err = String;
+
+    /// Parses a marker such as `RustyCOV 1792108800`, a bare timestamp or a `YYYY-MM-DD` date,
+    /// taken as midnight UTC.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let value = s.strip_prefix(Self::PREFIX).unwrap_or(s).trim();
+        if let Ok(embedded_at) = value.parse() {
+            return Ok(Self { embedded_at });
+        }
+
+        let date: Option<Vec<u32>> =
+            value.split('-').map(|part| if part.bytes().all(|byte| byte.is_ascii_digit()) { part.parse().ok() } else { None }).collect();
+        match date.as_deref() {
+            Some(&[year, month @ 1..=12, day]) if year >= 1970 && (1..=days_in_month(year, month)).contains(&day) => {
+                // Days from the epoch to the civil date, counting years from March so that the
+                // leap day comes last
+                let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+                let days = 365 * year + year / 4 - year / 100 + year / 400 + (153 * month + 2) / 5 + day - 1 - 719_468;
+                Ok(Self { embedded_at: days as u64 * 86_400 })
+            }
+            _ => Err(format!("Invalid provenance marker {:?}, expected a timestamp or a YYYY-MM-DD date", s)),
+        }
+    }
+}
+
+/// Returns the number of days in `month` (1-12) of `year`.
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl TryFrom<String> for Provenance {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Provenance> for String {
+    fn from(provenance: Provenance) -> Self {
+        provenance.to_string()
+    }
+}
+
+/// Files left alone in per-file mode because the front cover they already embed is good enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SkipExisting {
+    /// Any embedded front cover.
+    AnyFrontCover,
+    /// A front cover at least this many pixels wide and high.
+    MinSize { width: u32, height: u32 },
+    /// A front cover embedded by RustyCOV no earlier than the marker.
+    Since(Provenance),
+}
+
+impl SkipExisting {
+    /// Returns whether the [front cover](front_cover) embedded in an audio file meets the policy.
+    pub fn is_met_by(&self, file_path: &Path) -> Result<bool, RustyCovError> {
+        let tagged_file = read_tagged_file(file_path)?;
+        let Some(front) = front_cover(&tagged_file) else {
+            return Ok(false);
+        };
+
+        Ok(match self {
+            SkipExisting::AnyFrontCover => true,
+            SkipExisting::MinSize { width, height } => {
+                image_dimensions(front.data()).is_some_and(|(front_width, front_height)| front_width >= *width && front_height >= *height)
+            }
+            SkipExisting::Since(since) => front.description().and_then(Provenance::from_description).is_some_and(|marker| marker >= *since),
+        })
+    }
+}
+
+impl fmt::Display for SkipExisting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipExisting::AnyFrontCover => f.write_str("any"),
+            SkipExisting::MinSize { width, height } => write!(f, "{}x{}", width, height),
+            SkipExisting::Since(since) => write!(f, "since:{}", since.embedded_at),
+        }
+    }
+}
+
+impl FromStr for SkipExisting {
+    type Err = String;
+
+    /// Parses `any`, a size such as `1400` (square) or `1400x1000` (width by height), or `since:`
+    /// followed by a [provenance marker](Provenance).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("any") {
+            return Ok(SkipExisting::AnyFrontCover);
+        }
+        if let Some(marker) = s.strip_prefix("since:") {
+            return marker.parse().map(SkipExisting::Since);
+        }
+
+        let sizes: Result<Vec<u32>, _> = s.to_ascii_lowercase().split('x').map(|size| size.trim().parse::<u32>()).collect();
+        match sizes.as_deref() {
+            Ok([size]) => Ok(SkipExisting::MinSize { width: *size, height: *size }),
+            Ok([width, height]) => Ok(SkipExisting::MinSize { width: *width, height: *height }),
+            _ => Err(format!("Unknown skip policy {:?}, expected any, a size such as 1400x1400 or since:<marker>", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for SkipExisting {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SkipExisting> for String {
+    fn from(policy: SkipExisting) -> Self {
+        policy.to_string()
+    }
+}
+
+/// Result of [`embed_cover_image`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedCover {
+    /// Size in bytes of the embedded image after processing.
+    pub image_bytes: u64,
+    /// Tags the cover was written to.
+    pub tags: Vec<TagType>,
+}
+
+/// Returns whether tags of `tag_type` can hold pictures.
+pub fn supports_pictures(tag_type: TagType) -> bool {
+    matches!(tag_type, TagType::Id3v2 | TagType::Ape | TagType::Mp4Ilst | TagType::VorbisComments)
+}
+
 /// Embeds a cover image into an audio file.
 ///
-/// This function reads an audio file, downloads and processes an image from the given `image_url`,
-/// and embeds it as a front cover in the audio file. Optionally converts PNG images to JPEG,
-/// optimises JPEG images, and optimises PNG images if enabled.
+/// This function reads an audio file, processes `image_bytes` and embeds the image as the front
+/// cover of every tag selected by `target`, replacing their previous front covers, then saves the
+/// file once. The description of the replaced front cover is kept with the current [`Provenance`]
+/// marker appended. Optionally converts PNG images to JPEG, optimises JPEG images, and optimises
+/// PNG images if enabled.
 ///
 /// # Arguments
 ///
 /// * `audio_path` - Path to the audio file.
 /// * `image_bytes` - The image data to embed in the audio file.
+/// * `target` - Tags to embed the image into.
 /// * `convert_png_to_jpg` - Whether to convert PNG images to JPEG before embedding.
 /// * `jpeg_optimise` - Optimise the JPEG image using the specified quality (1-100) or None for no
 ///   optimisation.
 /// * `png_opt` - Whether to optimise PNG images.
+///
+/// # Returns
+///
+/// The size of the embedded image after processing and the tags that were updated.
 pub fn embed_cover_image<P: AsRef<Path>>(
     audio_path: P,
     image_bytes: Vec<u8>,
+    target: &TagTarget,
     convert_png_to_jpg: Arc<AtomicBool>,
     jpeg_optimise: Option<u8>,
     png_opt: Arc<AtomicBool>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<EmbeddedCover, RustyCovError> {
     let global_options = GlobalOptions::new().allocation_limit(ALLOCATION_LIMIT);
     apply_global_options(global_options);
 
     // Open the audio file with lofty
-    let mut tagged_file = Probe::open(&audio_path)?.read()?;
-
-    // Get or create the tag
-    let tag = match tagged_file.primary_tag_mut() {
-        Some(primary_tag) => primary_tag,
-        None => {
-            if let Some(first_tag) = tagged_file.first_tag_mut() {
-                first_tag
-            } else {
-                let tag_type = tagged_file.primary_tag_type();
-                tagged_file.insert_tag(Tag::new(tag_type));
-                tagged_file.primary_tag_mut().unwrap()
-            }
+    let mut tagged_file = read_tagged_file(audio_path.as_ref())?;
+
+    // Work out which tags to write
+    let primary_type = tagged_file.primary_tag_type();
+    let file_type = tagged_file.file_type();
+    let mut tag_types: Vec<TagType> = match target {
+        TagTarget::Primary => Vec::new(),
+        TagTarget::AllPictureTags => {
+            tagged_file.tags().iter().map(Tag::tag_type).filter(|tag_type| supports_pictures(*tag_type)).collect()
+        }
+        TagTarget::Types(types) => {
+            types.iter().copied().filter(|tag_type| supports_pictures(*tag_type) && file_type.supports_tag_type(*tag_type)).collect()
         }
     };
+    let mut seen = Vec::new();
+    tag_types.retain(|tag_type| {
+        let new = !seen.contains(tag_type);
+        seen.push(*tag_type);
+        new
+    });
+    if tag_types.is_empty() {
+        let existing = tagged_file.primary_tag().or_else(|| tagged_file.first_tag()).map(Tag::tag_type);
+        tag_types.push(existing.unwrap_or(primary_type));
+    }
 
     // Process the image and get the processed bytes and Picture
-    let (_, mut picture) = process_cover_image(image_bytes, &convert_png_to_jpg, jpeg_optimise, &png_opt)?;
+    let (_, mut picture) =
+        process_cover_image(image_bytes, &convert_png_to_jpg, jpeg_optimise, &png_opt).map_err(|e| e.with_path(&audio_path))?;
 
+    // Keep the description of the cover being replaced, marking it as embedded by us
+    let description = tag_types
+        .iter()
+        .filter_map(|tag_type| tagged_file.tag(*tag_type))
+        .flat_map(|tag| tag.pictures())
+        .find(|existing| is_front_cover(file_type, existing.pic_type()))
+        .and_then(Picture::description)
+        .map(str::to_string);
     picture.set_pic_type(PictureType::CoverFront);
+    picture.set_description(Some(Provenance::now().describe(description.as_deref())));
+    let image_bytes = picture.data().len() as u64;
 
-    // Remove any existing front cover, then add the new one
-    tag.remove_picture_type(PictureType::CoverFront);
-    tag.push_picture(picture);
+    // Remove any existing front cover from each tag, creating missing ones, then add the new one
+    for tag_type in &tag_types {
+        if tagged_file.tag(*tag_type).is_none() {
+            tagged_file.insert_tag(Tag::new(*tag_type));
+        }
+        if let Some(tag) = tagged_file.tag_mut(*tag_type) {
+            for index in (0..tag.pictures().len()).rev() {
+                if is_front_cover(file_type, tag.pictures()[index].pic_type()) {
+                    tag.remove_picture(index);
+                }
+            }
+            tag.push_picture(picture.clone());
+        }
+    }
 
-    // Save the tag back to the file
-    tag.save_to_path(audio_path, WriteOptions::new().respect_read_only(false))?;
+    // Save all tags back to the file at once
+    tagged_file
+        .save_to_path(&audio_path, WriteOptions::new().respect_read_only(false))
+        .map_err(|source| RustyCovError::TagWrite { path: audio_path.as_ref().to_path_buf(), source })?;
 
-    Ok(())
+    Ok(EmbeddedCover { image_bytes, tags: tag_types })
 }
 
 /// Processes the cover image based on the specified options.
@@ -90,7 +443,7 @@ pub fn process_cover_image(
     #[cfg_attr(not(feature = "jpeg-opt"), expect(unused_variables))] convert_png_to_jpg: &Arc<AtomicBool>,
     #[cfg_attr(not(feature = "jpeg-opt"), expect(unused_variables))] jpeg_optimise: Option<u8>,
     #[cfg_attr(not(feature = "png-opt"), expect(unused_variables))] png_opt: &Arc<AtomicBool>,
-) -> Result<(Vec<u8>, Picture), Box<dyn std::error::Error>> {
+) -> Result<(Vec<u8>, Picture), RustyCovError> {
     use std::io::Cursor;
     #[cfg_attr(not(any(feature = "jpeg-opt", feature = "png-opt")), expect(unused_imports))]
     use std::sync::atomic::Ordering;
@@ -100,7 +453,7 @@ pub fn process_cover_image(
     let mut cursor = Cursor::new(image_bytes);
 
     #[cfg_attr(not(any(feature = "jpeg-opt", feature = "png-opt")), expect(unused_mut))]
-    let mut picture = Picture::from_reader(&mut cursor)?;
+    let mut picture = Picture::from_reader(&mut cursor).map_err(RustyCovError::image_decode)?;
 
     match picture.mime_type() {
         Some(MimeType::Png) => {
@@ -112,7 +465,7 @@ pub fn process_cover_image(
             #[cfg(feature = "png-opt")]
             if picture.mime_type() == Some(&MimeType::Png) && png_opt.load(Ordering::Relaxed) {
                 optimise_png(&mut cursor)?;
-                picture = Picture::from_reader(&mut cursor)?;
+                picture = Picture::from_reader(&mut cursor).map_err(RustyCovError::image_decode)?;
             }
         }
         Some(MimeType::Jpeg) =>
@@ -120,7 +473,7 @@ pub fn process_cover_image(
             #[cfg(feature = "jpeg-opt")]
             if let Some(jpeg_quality) = jpeg_optimise {
                 optimise_jpeg(&mut cursor, jpeg_quality)?;
-                picture = Picture::from_reader(&mut cursor)?;
+                picture = Picture::from_reader(&mut cursor).map_err(RustyCovError::image_decode)?;
             }
         }
         _ => {}
@@ -130,21 +483,307 @@ pub fn process_cover_image(
     Ok((cursor.into_inner(), picture))
 }
 
-/// Removes any embedded front cover image from an audio file.
+/// Removes the embedded pictures selected by `policy` from every tag of an audio file.
 ///
-/// This function reads the specified audio file, removes the primary front cover image if present,
-/// and saves the changes back to the original file.
+/// The file is saved once, and only if a picture was removed.
 ///
 /// # Arguments
 ///
 /// * `file_path` - Path to the audio file.
-pub fn remove_embedded_art_from_file(file_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    let mut tagged_file = Probe::open(file_path)?.read()?;
-    if let Some(tag) = tagged_file.primary_tag_mut() {
-        while !tag.pictures().is_empty() {
-            tag.remove_picture(0);
+/// * `policy` - Which picture types to remove.
+///
+/// # Returns
+///
+/// The number of pictures removed.
+pub fn remove_embedded_art_from_file(file_path: &Path, policy: StripPolicy) -> Result<usize, RustyCovError> {
+    let mut tagged_file = read_tagged_file(file_path)?;
+    let file_type = tagged_file.file_type();
+    let tag_types: Vec<TagType> = tagged_file.tags().iter().map(Tag::tag_type).collect();
+
+    let mut removed = 0;
+    for tag_type in tag_types {
+        let Some(tag) = tagged_file.tag_mut(tag_type) else {
+            continue;
+        };
+        for index in (0..tag.pictures().len()).rev() {
+            if policy.strips(file_type, tag.pictures()[index].pic_type()) {
+                tag.remove_picture(index);
+                removed += 1;
+            }
+        }
+    }
+
+    if removed > 0 {
+        tagged_file
+            .save_to_path(file_path, WriteOptions::new().respect_read_only(false))
+            .map_err(|source| RustyCovError::TagWrite { path: file_path.to_path_buf(), source })?;
+    }
+    Ok(removed)
+}
+
+/// Counts the pictures embedded in all tags of an audio file.
+///
+/// # Returns
+///
+/// The number of pictures and their total size in bytes.
+pub fn embedded_picture_stats(file_path: &Path) -> Result<(usize, u64), RustyCovError> {
+    let tagged_file = read_tagged_file(file_path)?;
+    let pictures = tagged_file.tags().iter().flat_map(|tag| tag.pictures());
+    Ok(pictures.fold((0, 0), |(count, bytes), picture| (count + 1, bytes + picture.data().len() as u64)))
+}
+
+/// Returns the best front cover embedded in `files`, e.g. to write it out as the cover file of
+/// their folder.
+///
+/// Each file contributes its [front cover](front_cover). The picture with the largest resolution
+/// wins, then the one embedded in the most files. Files whose tags cannot be read are ignored.
+pub fn best_embedded_front_cover(files: &[PathBuf]) -> Option<Picture> {
+    // Distinct pictures along with the number of files embedding them
+    let mut candidates: Vec<(Picture, usize)> = Vec::new();
+    for file in files {
+        let Ok(tagged_file) = read_tagged_file(file) else {
+            continue;
+        };
+        if let Some(front) = front_cover(&tagged_file) {
+            match candidates.iter_mut().find(|(candidate, _)| candidate.data() == front.data()) {
+                Some((_, count)) => *count += 1,
+                None => candidates.push(((*front).clone(), 1)),
+            }
+        }
+    }
+
+    let pixels = |picture: &Picture| image_dimensions(picture.data()).map_or(0, |(width, height)| width as u64 * height as u64);
+    let mut best: Option<(Picture, usize)> = None;
+    for (picture, count) in candidates {
+        if best.as_ref().is_none_or(|(best, best_count)| (pixels(&picture), count) > (pixels(best), *best_count)) {
+            best = Some((picture, count));
+        }
+    }
+    best.map(|(picture, _)| picture)
+}
+
+/// Returns the front cover embedded in a file: its first picture that [is a front
+/// cover](is_front_cover).
+pub fn front_cover(tagged_file: &TaggedFile) -> Option<&Picture> {
+    let file_type = tagged_file.file_type();
+    tagged_file.tags().iter().flat_map(|tag| tag.pictures()).find(|picture| is_front_cover(file_type, picture.pic_type()))
+}
+
+/// Returns whether a picture of `picture_type` is the front cover of a file of `file_type`.
+///
+/// MP4 files do not store picture types, so lofty reads every picture of an M4A file as
+/// [`PictureType::Other`]; those count as front covers.
+pub fn is_front_cover(file_type: FileType, picture_type: PictureType) -> bool {
+    picture_type == PictureType::CoverFront || (file_type == FileType::Mp4 && picture_type == PictureType::Other)
+}
+
+/// Returns the file extension for the MIME type of `picture`, falling back to the extension of its
+/// image format and then to `jpg`.
+pub fn picture_extension(picture: &Picture) -> &'static str {
+    match picture.mime_type() {
+        Some(MimeType::Jpeg) => "jpg",
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Gif) => "gif",
+        Some(MimeType::Bmp) => "bmp",
+        Some(MimeType::Tiff) => "tiff",
+        _ => image_extension(picture.data()).unwrap_or("jpg"),
+    }
+}
+
+/// Album-level tag values of an audio file, used to query cover sources.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlbumTags {
+    pub album_artist: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<u32>,
+    pub musicbrainz_release_id: Option<String>,
+}
+
+/// Reads the album artist, artist, album, year and MusicBrainz release ID from the primary tag of
+/// an audio file, falling back to the first tag that has them.
+pub fn read_album_tags(file_path: &Path) -> Result<AlbumTags, RustyCovError> {
+    let tagged_file = read_tagged_file(file_path)?;
+    let tags: Vec<&Tag> = tagged_file.primary_tag().into_iter().chain(tagged_file.tags()).collect();
+    let first = |get: fn(&Tag) -> Option<String>| tags.iter().find_map(|tag| get(tag).filter(|value| !value.trim().is_empty()));
+
+    Ok(AlbumTags {
+        album_artist: first(|tag| tag.get_string(&ItemKey::AlbumArtist).map(str::to_string)),
+        artist: first(|tag| tag.artist().map(|value| value.into_owned())),
+        album: first(|tag| tag.album().map(|value| value.into_owned())),
+        year: tags.iter().find_map(|tag| tag.year()),
+        musicbrainz_release_id: first(|tag| tag.get_string(&ItemKey::MusicBrainzReleaseId).map(str::to_string)),
+    })
+}
+
+/// Returns the most common album artist, artist, album and year across `files`, so that a few
+/// tracks with a guest artist or a missing tag do not change the query for the whole folder. Ties
+/// go to the value seen first; files whose tags cannot be read are ignored.
+pub fn most_common_album_tags(files: &[PathBuf]) -> AlbumTags {
+    let tags: Vec<AlbumTags> = files.iter().filter_map(|file| read_album_tags(file).ok()).collect();
+
+    AlbumTags {
+        album_artist: most_common(tags.iter().filter_map(|tags| tags.album_artist.as_ref())),
+        artist: most_common(tags.iter().filter_map(|tags| tags.artist.as_ref())),
+        album: most_common(tags.iter().filter_map(|tags| tags.album.as_ref())),
+        year: most_common(tags.iter().filter_map(|tags| tags.year.as_ref())),
+        musicbrainz_release_id: most_common(tags.iter().filter_map(|tags| tags.musicbrainz_release_id.as_ref())),
+    }
+}
+
+/// Returns the value occurring most often, preferring the first one seen on a tie.
+fn most_common<'a, T: PartialEq + Clone + 'a>(values: impl Iterator<Item = &'a T>) -> Option<T> {
+    let mut counts: Vec<(&T, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    // `max_by_key` returns the last maximum, so walk backwards to prefer the first one seen
+    counts.into_iter().rev().max_by_key(|(_, count)| *count).map(|(value, _)| value.clone())
+}
+
+/// Returns a stable name for `tag_type`, used when tag types are stored or passed on the command
+/// line.
+pub fn tag_type_name(tag_type: TagType) -> &'static str {
+    match tag_type {
+        TagType::Ape => "ape",
+        TagType::Id3v1 => "id3v1",
+        TagType::Id3v2 => "id3v2",
+        TagType::Mp4Ilst => "mp4ilst",
+        TagType::VorbisComments => "vorbis",
+        TagType::RiffInfo => "riffinfo",
+        TagType::AiffText => "aifftext",
+        _ => "unknown",
+    }
+}
+
+/// Parses a name returned by [`tag_type_name`] (case-insensitive).
+pub fn tag_type_from_name(name: &str) -> Option<TagType> {
+    match name.to_ascii_lowercase().as_str() {
+        "ape" => Some(TagType::Ape),
+        "id3v1" => Some(TagType::Id3v1),
+        "id3v2" => Some(TagType::Id3v2),
+        "mp4ilst" => Some(TagType::Mp4Ilst),
+        "vorbis" => Some(TagType::VorbisComments),
+        "riffinfo" => Some(TagType::RiffInfo),
+        "aifftext" => Some(TagType::AiffText),
+        _ => None,
+    }
+}
+
+/// Opens and reads the tags of an audio file, mapping failures to [`RustyCovError::TagRead`].
+pub(crate) fn read_tagged_file(file_path: &Path) -> Result<TaggedFile, RustyCovError> {
+    Probe::open(file_path)
+        .and_then(|probe| probe.read())
+        .map_err(|source| RustyCovError::TagRead { path: file_path.to_path_buf(), source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_common_prefers_the_first_value_on_ties() {
+        let values = ["b", "a", "a", "b", "c"];
+        assert_eq!(most_common(values.iter()), Some("b"));
+        assert_eq!(most_common(["x", "y", "y"].iter()), Some("y"));
+        assert_eq!(most_common([2011, 2011, 2012].iter()), Some(2011));
+        assert_eq!(most_common(std::iter::empty::<&u32>()), None);
+    }
+
+    #[test]
+    fn parses_tag_targets() {
+        assert_eq!("Primary".parse(), Ok(TagTarget::Primary));
+        assert_eq!(" all ".parse(), Ok(TagTarget::AllPictureTags));
+        assert_eq!("id3v2, APE".parse(), Ok(TagTarget::Types(vec![TagType::Id3v2, TagType::Ape])));
+        assert_eq!("id3v2,flac".parse::<TagTarget>(), Err("Unknown tag type \"flac\"".to_string()));
+        assert!("".parse::<TagTarget>().is_err());
+
+        for target in ["primary", "all", "vorbis,mp4ilst"] {
+            assert_eq!(target.parse::<TagTarget>().unwrap().to_string(), target);
         }
-        tag.save_to_path(file_path, WriteOptions::new().respect_read_only(false))?;
     }
-    Ok(())
+
+    #[test]
+    fn parses_strip_policies() {
+        assert_eq!("FRONT".parse(), Ok(StripPolicy::FrontCover));
+        assert_eq!(" keep-booklet".parse(), Ok(StripPolicy::KeepBookletAndBack));
+        assert_eq!("all".parse(), Ok(StripPolicy::All));
+        assert!("back".parse::<StripPolicy>().is_err());
+
+        for policy in [StripPolicy::FrontCover, StripPolicy::KeepBookletAndBack, StripPolicy::All] {
+            assert_eq!(policy.to_string().parse(), Ok(policy));
+        }
+    }
+
+    #[test]
+    fn strip_policies_select_picture_types() {
+        let types = [PictureType::CoverFront, PictureType::CoverBack, PictureType::Leaflet, PictureType::Artist];
+        let stripped =
+            |policy: StripPolicy| types.into_iter().filter(|picture_type| policy.strips(FileType::Mpeg, *picture_type)).collect::<Vec<_>>();
+
+        assert_eq!(stripped(StripPolicy::FrontCover), [PictureType::CoverFront]);
+        assert_eq!(stripped(StripPolicy::KeepBookletAndBack), [PictureType::CoverFront, PictureType::Artist]);
+        assert_eq!(stripped(StripPolicy::All), types);
+
+        // Every MP4 picture is read as untyped
+        assert!(StripPolicy::FrontCover.strips(FileType::Mp4, PictureType::Other));
+        assert!(!StripPolicy::FrontCover.strips(FileType::Mpeg, PictureType::Other));
+    }
+
+    #[test]
+    fn parses_skip_policies() {
+        assert_eq!("ANY".parse(), Ok(SkipExisting::AnyFrontCover));
+        assert_eq!("1400".parse(), Ok(SkipExisting::MinSize { width: 1400, height: 1400 }));
+        assert_eq!("1400X1000".parse(), Ok(SkipExisting::MinSize { width: 1400, height: 1000 }));
+        assert_eq!("since:1792108800".parse(), Ok(SkipExisting::Since(Provenance { embedded_at: 1_792_108_800 })));
+        assert_eq!("since:RustyCOV 5".parse(), Ok(SkipExisting::Since(Provenance { embedded_at: 5 })));
+        for invalid in ["", "big", "1400x", "1x2x3", "since:", "since:yesterday"] {
+            assert!(invalid.parse::<SkipExisting>().is_err(), "{:?} parsed", invalid);
+        }
+
+        for policy in ["any", "1400x1000", "since:5"] {
+            assert_eq!(policy.parse::<SkipExisting>().unwrap().to_string(), policy);
+        }
+    }
+
+    #[test]
+    fn parses_provenance_dates_as_midnight_utc() {
+        let is_leap = |year: u64| year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        let days_before = |year: u64, month: u64| {
+            let month_days = [31, if is_leap(year) { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+            (1970..year).map(|year| if is_leap(year) { 366 } else { 365 }).sum::<u64>() + month_days[..month as usize - 1].iter().sum::<u64>()
+        };
+
+        for (date, year, month, day) in [("1970-01-01", 1970, 1, 1), ("2000-02-29", 2000, 2, 29), ("2026-10-16", 2026, 10, 16)] {
+            let expected = (days_before(year, month) + day - 1) * 86_400;
+            assert_eq!(date.parse::<Provenance>(), Ok(Provenance { embedded_at: expected }), "{}", date);
+        }
+        for invalid in ["1969-12-31", "2026-13-01", "2026-00-10", "2026-04-31", "2026-02-29", "2100-02-29", "2026-10-00"] {
+            assert!(invalid.parse::<Provenance>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn provenance_dates_need_exactly_three_numeric_parts() {
+        assert!("2024-02-29".parse::<Provenance>().is_ok());
+        for invalid in ["2026-xx-10-01", "2026-10-01-xx", "2026-10", "2026--10-01", "2026-10-01-", "2026-+1-01", "2026-10-1x"] {
+            assert!(invalid.parse::<Provenance>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn provenance_keeps_the_rest_of_the_description() {
+        let marker = Provenance { embedded_at: 20 };
+        assert_eq!(marker.describe(None), "RustyCOV 20");
+        assert_eq!(marker.describe(Some(" ")), "RustyCOV 20");
+        assert_eq!(marker.describe(Some("Scan by Alice")), "Scan by Alice RustyCOV 20");
+        assert_eq!(marker.describe(Some("Scan by Alice RustyCOV 10")), "Scan by Alice RustyCOV 20");
+
+        assert_eq!(Provenance::from_description("Scan by Alice RustyCOV 10"), Some(Provenance { embedded_at: 10 }));
+        assert_eq!(Provenance::from_description("About RustyCOV itself"), None);
+        assert_eq!(Provenance::from_description("Front"), None);
+    }
 }
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Patterns tried after the user's own, see [`NamePattern`].
+pub const BUILTIN_PATTERNS: &[&str] = &[
+    "{artist} - {album} - {track} - {title}",
+    "{track} - {artist} - {album}",
+    "{artist}/{year} - {album}/…",
+    "{artist} - {year} - {album}/…",
+    "{artist} - {album} ({year})/…",
+];
+
+/// A field a [`NamePattern`] can capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+    /// `{_}`, matches any text that is thrown away.
+    Ignore,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "artist" => Some(Field::Artist),
+            "albumartist" => Some(Field::AlbumArtist),
+            "album" => Some(Field::Album),
+            "title" => Some(Field::Title),
+            "track" => Some(Field::Track),
+            "disc" => Some(Field::Disc),
+            "year" => Some(Field::Year),
+            "_" => Some(Field::Ignore),
+            _ => None,
+        }
+    }
+
+    /// Returns whether `value` is an acceptable capture for this field.
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            Field::Track | Field::Disc => (1..=3).contains(&value.len()) && value.bytes().all(|b| b.is_ascii_digit()),
+            Field::Year => value.len() == 4 && value.bytes().all(|b| b.is_ascii_digit()),
+            _ => !value.trim().is_empty(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// Errors returned when parsing a [`NamePattern`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NamePatternError {
+    #[error("Unknown field {{{0}}} in name pattern, expected artist, albumartist, album, title, track, disc, year or _")]
+    UnknownField(String),
+    #[error("Unclosed {{ in name pattern {0:?}")]
+    Unclosed(String),
+    #[error("Empty name pattern")]
+    Empty,
+}
+
+/// Pattern extracting artist, album and other fields from a file name and its parent directories.
+///
+/// Fields are written as `{artist}`, `{albumartist}`, `{album}`, `{title}`, `{track}`, `{disc}`,
+/// `{year}` or `{_}` (ignored), everything else must match literally. `{track}` and `{disc}`
+/// match one to three digits and `{year}` four. The last `/`-separated segment is matched against
+/// the file stem and each segment before it against the next parent directory up, so
+/// `{artist}/{year} - {album}/{track} {title}` matches `Artist/2011 - Album/01 Song.flac`. A
+/// segment of `…`, `...` or `*` matches any name, so a last segment of `…` matches any file name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NamePattern {
+    source: String,
+    /// Segments from the file stem up to the outermost directory; `None` matches anything.
+    segments: Vec<Option<Vec<Token>>>,
+}
+
+/// Fields captured by a [`NamePattern`], cleaned with [`clean_name`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedName {
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<u32>,
+}
+
+impl NamePattern {
+    /// Matches the pattern against `path`, returning the captured fields.
+    pub fn parse_path(&self, path: &Path) -> Option<ParsedName> {
+        let mut names = Vec::new();
+        names.push(path.file_stem()?.to_str()?);
+        names.extend(path.parent()?.components().rev().filter_map(|component| match component {
+            std::path::Component::Normal(name) => name.to_str(),
+            _ => None,
+        }));
+        if names.len() < self.segments.len() {
+            return None;
+        }
+
+        let mut parsed = ParsedName::default();
+        for (segment, name) in self.segments.iter().zip(names) {
+            let Some(tokens) = segment else {
+                continue;
+            };
+            let mut captures = Vec::new();
+            if !match_tokens(tokens, name, &mut captures) {
+                return None;
+            }
+            for (field, value) in captures {
+                parsed.set(field, value);
+            }
+        }
+        Some(parsed)
+    }
+}
+
+impl ParsedName {
+    fn set(&mut self, field: Field, value: &str) {
+        let text = |slot: &mut Option<String>| {
+            let cleaned = clean_name(value);
+            if slot.is_none() && !cleaned.is_empty() {
+                *slot = Some(cleaned);
+            }
+        };
+        let number = |slot: &mut Option<u32>| {
+            if slot.is_none() {
+                *slot = value.parse().ok();
+            }
+        };
+        match field {
+            Field::Artist => text(&mut self.artist),
+            Field::AlbumArtist => text(&mut self.album_artist),
+            Field::Album => text(&mut self.album),
+            Field::Title => text(&mut self.title),
+            Field::Track => number(&mut self.track),
+            Field::Disc => number(&mut self.disc),
+            Field::Year => number(&mut self.year),
+            Field::Ignore => {}
+        }
+    }
+}
+
+/// Matches `tokens` against the whole of `input`, trying the shortest captures first.
+fn match_tokens<'a>(tokens: &[Token], input: &'a str, captures: &mut Vec<(Field, &'a str)>) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return input.is_empty();
+    };
+    match token {
+        Token::Literal(literal) => input.strip_prefix(literal.as_str()).is_some_and(|input| match_tokens(rest, input, captures)),
+        Token::Field(field) => {
+            for (end, _) in input.char_indices().skip(1).chain(std::iter::once((input.len(), ' '))) {
+                let value = &input[..end];
+                if !field.accepts(value) {
+                    continue;
+                }
+                captures.push((*field, value));
+                if match_tokens(rest, &input[end..], captures) {
+                    return true;
+                }
+                captures.pop();
+            }
+            false
+        }
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = NamePatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(NamePatternError::Empty);
+        }
+
+        let mut segments = Vec::new();
+        for segment in s.split('/').rev() {
+            if matches!(segment, "…" | "..." | "*") {
+                segments.push(None);
+                continue;
+            }
+
+            let mut tokens = Vec::new();
+            let mut rest = segment;
+            while let Some(open) = rest.find('{') {
+                if open > 0 {
+                    tokens.push(Token::Literal(rest[..open].to_string()));
+                }
+                let close = rest[open..].find('}').ok_or_else(|| NamePatternError::Unclosed(s.to_string()))? + open;
+                let name = &rest[open + 1..close];
+                let field = Field::from_name(&name.to_ascii_lowercase()).ok_or_else(|| NamePatternError::UnknownField(name.to_string()))?;
+                tokens.push(Token::Field(field));
+                rest = &rest[close + 1..];
+            }
+            if !rest.is_empty() {
+                tokens.push(Token::Literal(rest.to_string()));
+            }
+            segments.push(Some(tokens));
+        }
+
+        Ok(Self { source: s.to_string(), segments })
+    }
+}
+
+impl TryFrom<String> for NamePattern {
+    type Error = NamePatternError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<NamePattern> for String {
+    fn from(pattern: NamePattern) -> Self {
+        pattern.source
+    }
+}
+
+impl fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Returns the [`BUILTIN_PATTERNS`] parsed.
+pub fn builtin_patterns() -> Vec<NamePattern> {
+    BUILTIN_PATTERNS.iter().map(|pattern| pattern.parse().expect("Built-in name patterns are valid")).collect()
+}
+
+/// Removes bracketed tags such as `[FLAC]` or `(2011 Remaster)` and collapses whitespace.
+pub fn clean_name(name: &str) -> String {
+    let mut cleaned = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' if depth > 0 => depth -= 1,
+            _ if depth == 0 => cleaned.push(c),
+            _ => {}
+        }
+    }
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    cleaned.trim_matches(|c: char| c == '-' || c == '_' || c == '.' || c.is_whitespace()).to_string()
+}
+
+/// Removes a leading track number such as `01 `, `01. `, `1-01 ` or `07 - ` from a file stem.
+///
+/// Numbers of four or more digits are kept since they are more likely a year or part of the name,
+/// as is a number that makes up the whole stem.
+pub fn strip_track_number(stem: &str) -> &str {
+    let is_separator = |c: char| c.is_whitespace() || matches!(c, '.' | '-' | '_' | ')');
+
+    let mut rest = stem;
+    // Disc and track, e.g. "1-01"
+    for _ in 0..2 {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if !(1..=3).contains(&digits) {
+            break;
+        }
+        let after = &rest[digits..];
+        if !after.starts_with(is_separator) {
+            break;
+        }
+        rest = after.trim_start_matches(is_separator);
+    }
+
+    if rest.is_empty() { stem } else { rest }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(pattern: &str, path: &str) -> Option<ParsedName> {
+        pattern.parse::<NamePattern>().unwrap().parse_path(Path::new(path))
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert_eq!("{artist - {album}".parse::<NamePattern>(), Err(NamePatternError::UnknownField("artist - {album".to_string())));
+        assert_eq!("{artist} - {album".parse::<NamePattern>(), Err(NamePatternError::Unclosed("{artist} - {album".to_string())));
+        assert_eq!("{genre}".parse::<NamePattern>(), Err(NamePatternError::UnknownField("genre".to_string())));
+        assert_eq!("  ".parse::<NamePattern>(), Err(NamePatternError::Empty));
+        assert!("{Artist} - {ALBUM}".parse::<NamePattern>().is_ok());
+    }
+
+    #[test]
+    fn backtracks_when_a_separator_repeats_inside_a_field() {
+        let parsed = parse("{artist} - {album} - {track} - {title}", "Jay - Z - Live - One - 03 - Song.flac").unwrap();
+        assert_eq!(parsed.artist.as_deref(), Some("Jay"));
+        assert_eq!(parsed.album.as_deref(), Some("Z - Live - One"));
+        assert_eq!(parsed.track, Some(3));
+        assert_eq!(parsed.title.as_deref(), Some("Song"));
+
+        assert_eq!(parse("{artist} - {track} - {title}", "Artist - Song.flac"), None);
+    }
+
+    #[test]
+    fn wildcard_segments_match_any_name() {
+        for wildcard in ["…", "...", "*"] {
+            let parsed = parse(&format!("{{artist}}/{{year}} - {{album}}/{}", wildcard), "Music/Artist/2011 - Album/01 x.flac").unwrap();
+            assert_eq!(parsed.artist.as_deref(), Some("Artist"));
+            assert_eq!(parsed.album.as_deref(), Some("Album"));
+            assert_eq!(parsed.year, Some(2011));
+        }
+
+        let parsed = parse("{artist}/*/{title}", "Artist/Whatever/Song.flac").unwrap();
+        assert_eq!(parsed.artist.as_deref(), Some("Artist"));
+        assert_eq!(parsed.title.as_deref(), Some("Song"));
+        assert_eq!(parse("{artist}/{album}/…", "Album/01.flac"), None);
+    }
+
+    #[test]
+    fn cleans_nested_brackets() {
+        assert_eq!(clean_name("Album [Deluxe (2011 Remaster)] Extra"), "Album Extra");
+        assert_eq!(clean_name("  Album {Disc [1]} - "), "Album");
+        assert_eq!(clean_name("Song) Name"), "Song) Name");
+    }
+
+    #[test]
+    fn strips_track_numbers() {
+        assert_eq!(strip_track_number("01 Song"), "Song");
+        assert_eq!(strip_track_number("01. Song"), "Song");
+        assert_eq!(strip_track_number("07 - Song"), "Song");
+        assert_eq!(strip_track_number("1-01 Song"), "Song");
+        assert_eq!(strip_track_number("2011 Song"), "2011 Song");
+        assert_eq!(strip_track_number("1999"), "1999");
+        assert_eq!(strip_track_number("01"), "01");
+        assert_eq!(strip_track_number("01 "), "01 ");
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use lofty::tag::TagType;
+use serde::Serialize;
+
+use crate::error::RustyCovError;
+use crate::plan::Plan;
+use crate::report::ImageSizes;
+use crate::scan::Scan;
+use crate::structs::{CoverUrl, CoverUrlKind, Picked};
+
+/// What the target of a [`RunObserver`] event is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    /// A directory, in album folder and extract mode.
+    Folder,
+    /// A single file, in per-file mode.
+    File,
+}
+
+/// Counts reported once a run has finished.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RunSummary {
+    /// Whether the run used album folder mode, or extract mode stripping the files.
+    pub album_mode: bool,
+    /// Number of folders that received a cover file (album folder and extract mode only).
+    pub folders_edited: usize,
+    /// Number of files that were embedded into or had their art removed.
+    pub files_edited: usize,
+    /// Number of files left alone because their embedded art meets the skip policy (per-file mode
+    /// only).
+    pub files_skipped: usize,
+    /// Whether the run was cancelled, in which case the counts are partial.
+    pub cancelled: bool,
+}
+
+/// Receives progress events from [`run_with`](crate::run_with).
+///
+/// Every method has an empty default implementation, so implementors only override the events
+/// they care about. Events may be delivered from worker threads, hence the `Send + Sync` bound.
+pub trait RunObserver: Send + Sync {
+    /// A dependency binary is being downloaded.
+    fn dependency_downloading(&self, _name: &str) {}
+
+    /// A downloaded dependency archive is being extracted.
+    fn dependency_extracting(&self, _name: &str) {}
+
+    /// A dry run has built its plan; nothing else will be reported.
+    fn plan_ready(&self, _plan: &Plan) {}
+
+    /// A scan has built its report; nothing else will be reported.
+    fn scan_ready(&self, _scan: &Scan) {}
+
+    /// No supported audio/video files were found for the input.
+    fn no_files_found(&self) {}
+
+    /// A query override applies to a directory containing none of the files of the run.
+    fn query_override_unused(&self, _dir: &Path) {}
+
+    /// Original pictures are being backed up to `session_dir`, which can be passed to
+    /// [`undo`](crate::backup::undo).
+    fn backup_session_started(&self, _session_dir: &Path) {}
+
+    /// Processing of a directory has started.
+    fn folder_started(&self, _dir: &Path) {}
+
+    /// A directory was skipped because its cover file already exists.
+    fn folder_skipped(&self, _dir: &Path) {}
+
+    /// `target` was skipped because the resumed journal records it as done.
+    fn already_done(&self, _target: &Path) {}
+
+    /// `path` was skipped because its embedded front cover meets the skip policy.
+    fn existing_art_kept(&self, _path: &Path) {}
+
+    /// A cover was picked for `target`, which is a directory in album folder mode and a file
+    /// otherwise.
+    fn cover_picked(&self, _target: &Path, _kind: TargetKind, _picked: &Picked) {}
+
+    /// No cover could be picked for `target`.
+    fn no_cover_found(&self, _target: &Path, _kind: TargetKind) {}
+
+    /// The cover about to be reported with [`RunObserver::cover_picked`] was chosen automatically
+    /// with the given [score](crate::rank::RankingRules).
+    fn cover_auto_picked(&self, _target: &Path, _score: f64) {}
+
+    /// The best candidate for `target` scored below the automatic picking threshold, so `target`
+    /// is left for an interactive run.
+    fn cover_deferred(&self, _target: &Path, _best_score: f64) {}
+
+    /// covit is waiting for the user to pick a cover for `target` at `address`.
+    fn covit_listening(&self, _target: &Path, _address: &str) {}
+
+    /// The user closed the covit tab without picking a cover.
+    fn user_closed_tab(&self, _target: &Path) {}
+
+    /// No cover was picked for `target` within the pick timeout, so covit was stopped.
+    fn covit_timed_out(&self, _target: &Path) {}
+
+    /// A download has started; `total` is the size in bytes if the server reported it.
+    fn download_started(&self, _url: &str, _total: Option<u64>) {}
+
+    /// `downloaded` bytes of `url` have been received so far.
+    fn download_progress(&self, _url: &str, _downloaded: u64, _total: Option<u64>) {}
+
+    /// A download has completed.
+    fn download_finished(&self, _url: &str) {}
+
+    /// The cover for `target` was downloaded from `url`, which is not the big cover if that one
+    /// failed or was too small.
+    fn cover_downloaded(&self, _target: &Path, _url: &CoverUrl) {}
+
+    /// The cover file was written in album folder mode.
+    fn sidecar_saved(&self, _path: &Path, _sizes: ImageSizes) {}
+
+    /// The cover about to be reported with [`RunObserver::file_embedded`] was written to `tags`.
+    fn tags_updated(&self, _path: &Path, _tags: &[TagType]) {}
+
+    /// A cover was embedded into `path`.
+    fn file_embedded(&self, _path: &Path, _sizes: ImageSizes) {}
+
+    /// Embedded art was removed from `path`.
+    fn art_stripped(&self, _path: &Path) {}
+
+    /// `path` held no embedded art matching the strip policy and was left unchanged.
+    fn art_unchanged(&self, _path: &Path) {}
+
+    /// A non-fatal error occurred; the run continues with the next item.
+    fn error(&self, _error: &RustyCovError) {}
+
+    /// The run has finished.
+    fn run_finished(&self, _summary: &RunSummary) {}
+}
+
+/// Observer that ignores every event, for headless use.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl RunObserver for NoopObserver {}
+
+/// Observer that reports progress on the terminal, drawing download progress bars.
+#[derive(Debug, Default)]
+pub struct TerminalObserver {
+    progress_bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl TerminalObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RunObserver for TerminalObserver {
+    fn dependency_downloading(&self, name: &str) {
+        println!("Downloading {}...", name);
+    }
+
+    fn dependency_extracting(&self, name: &str) {
+        println!("Extracting {}...", name);
+    }
+
+    fn plan_ready(&self, plan: &Plan) {
+        println!("{}", plan);
+    }
+
+    fn scan_ready(&self, scan: &Scan) {
+        println!("{}", scan);
+    }
+
+    fn no_files_found(&self) {
+        eprintln!("No supported audio/video files were found exiting.");
+    }
+
+    fn query_override_unused(&self, dir: &Path) {
+        eprintln!("Warning: the query override for {:?} matches none of the input files.", dir);
+    }
+
+    fn backup_session_started(&self, session_dir: &Path) {
+        println!("Backing up original pictures to {:?}, undo with `undo {}`.", session_dir, session_dir.display());
+    }
+
+    fn folder_skipped(&self, dir: &Path) {
+        println!("Album art already exists in {:?}, skipping.", dir);
+    }
+
+    fn already_done(&self, target: &Path) {
+        println!("Already finished {:?} in a previous run, skipping.", target);
+    }
+
+    fn existing_art_kept(&self, path: &Path) {
+        println!("Embedded art of {:?} is good enough, skipping.", path);
+    }
+
+    fn cover_picked(&self, target: &Path, kind: TargetKind, picked: &Picked) {
+        if kind == TargetKind::Folder {
+            println!("Folder: {:?}", target);
+        }
+        println!(
+            "Artist: {}\nTitle: {}\nDate: {}\nCover Type: {}\nImage Size: {} bytes\nDimensions: {}x{}\nBig Cover URL: {}\n",
+            picked.release_info.artist.as_deref().unwrap_or("Unknown Artist"),
+            picked.release_info.title.as_deref().unwrap_or("Unknown Title"),
+            picked.release_info.date.as_deref().unwrap_or("Unknown Date"),
+            picked.cover_info.format.as_deref().unwrap_or("Unknown Format"),
+            picked.cover_info.size.unwrap_or(0),
+            picked.cover_info.width.unwrap_or(0),
+            picked.cover_info.height.unwrap_or(0),
+            picked.big_cover_url
+        );
+    }
+
+    fn no_cover_found(&self, target: &Path, kind: TargetKind) {
+        if kind == TargetKind::Folder {
+            println!("No cover info found for folder {:?}", target);
+        } else {
+            println!("No cover info found for {:?}", target);
+        }
+    }
+
+    fn cover_auto_picked(&self, target: &Path, score: f64) {
+        println!("Picked automatically for {:?} with a score of {:.2}", target, score);
+    }
+
+    fn cover_deferred(&self, target: &Path, best_score: f64) {
+        println!("Deferred {:?}: the best cover scored {:.2}, below the threshold", target, best_score);
+    }
+
+    fn covit_listening(&self, target: &Path, address: &str) {
+        println!("Waiting for a cover pick for {:?} on {}", target, address);
+    }
+
+    fn user_closed_tab(&self, _target: &Path) {
+        println!("User closed the tab");
+    }
+
+    fn covit_timed_out(&self, target: &Path) {
+        println!("No cover picked in time for {:?}", target);
+    }
+
+    fn download_started(&self, url: &str, total: Option<u64>) {
+        let pb = if let Some(total) = total {
+            let pb = ProgressBar::new(total);
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {binary_bytes_per_sec} {bar:40} {binary_bytes} / {binary_total_bytes}")
+                    .expect("Failed to create ProgressStyle object")
+                    .progress_chars("#-"),
+            );
+            pb
+        } else {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {spinner} Received {binary_bytes}")
+                    .expect("Failed to create ProgressStyle object")
+                    .progress_chars("#-"),
+            );
+            pb
+        };
+        self.progress_bars.lock().unwrap().insert(url.to_string(), pb);
+    }
+
+    fn download_progress(&self, url: &str, downloaded: u64, _total: Option<u64>) {
+        if let Some(pb) = self.progress_bars.lock().unwrap().get(url) {
+            pb.set_position(downloaded);
+        }
+    }
+
+    fn download_finished(&self, url: &str) {
+        if let Some(pb) = self.progress_bars.lock().unwrap().remove(url) {
+            pb.finish();
+        }
+    }
+
+    fn cover_downloaded(&self, target: &Path, url: &CoverUrl) {
+        if url.kind != CoverUrlKind::Big {
+            println!("Big cover unavailable for {:?}, used the {} cover {}", target, url.kind, url.url);
+        }
+    }
+
+    fn sidecar_saved(&self, path: &Path, _sizes: ImageSizes) {
+        println!("Saved album art to {:?}", path);
+    }
+
+    fn art_stripped(&self, path: &Path) {
+        println!("Removed embedded art from {:?}", path);
+    }
+
+    fn art_unchanged(&self, path: &Path) {
+        println!("No embedded art to remove from {:?}", path);
+    }
+
+    fn error(&self, error: &RustyCovError) {
+        eprintln!("{}", error);
+    }
+
+    fn run_finished(&self, summary: &RunSummary) {
+        if summary.cancelled {
+            println!("\nCancelled, partial totals follow.");
+        }
+        if summary.album_mode {
+            println!(
+                "\nTotal: {} folder(s) finished, {} file(s) removed metadata.",
+                summary.folders_edited, summary.files_edited
+            );
+        } else if summary.folders_edited > 0 {
+            println!("\nTotal: {} folder(s) finished.", summary.folders_edited);
+        } else if summary.files_skipped > 0 {
+            println!("\nTotal: {} file(s) finished, {} file(s) skipped with existing art.", summary.files_edited, summary.files_skipped);
+        } else {
+            println!("\nTotal: {} file(s) finished.", summary.files_edited);
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancellationToken;
+use crate::lofty::{SkipExisting, StripPolicy, TagTarget};
+use crate::name_pattern::NamePattern;
+use crate::observer::{NoopObserver, RunObserver};
+use crate::query::{Country, QueryConfig, QueryOverride, QuerySource};
+use crate::rank::RankingRules;
+use crate::source::CoverSource;
+use crate::structs::CoverUrlKind;
+
+/// Default COV website opened by covit when no address is supplied.
+pub const DEFAULT_COV_ADDRESS: &str = "https://covers.musichoarders.xyz";
+
+/// Options controlling a single [`run_with`](crate::run_with) invocation.
+///
+/// The defaults match the behaviour of `rusty_cov_cli` when no flags are passed. Use
+/// [`RunOptions::builder`] to construct a value with named setters so that adding new options
+/// does not break existing callers.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunOptions {
+    /// Input directory or file to process.
+    pub input: String,
+    /// Address of the COV website for launch. `None` uses [`DEFAULT_COV_ADDRESS`].
+    pub cov_address: Option<String>,
+    /// Whether to convert PNG images to JPEG before embedding.
+    pub convert_png_to_jpg: bool,
+    /// Optimise JPEG images with the specified quality (1-100) or `None` for no optimisation. Other
+    /// qualities are rejected when the run starts.
+    pub jpeg_optimise: Option<u8>,
+    /// Whether to optimise PNG images.
+    pub png_opt: bool,
+    /// Cover file name to write in album folder mode, or `None` to embed into each file.
+    pub album_folder_mode: Option<String>,
+    /// Cover file name to write the best embedded front cover of each folder to, without covit or
+    /// network access. Takes precedence over the other modes; `None` looks covers up.
+    pub extract: Option<String>,
+    /// Whether to remove the embedded art selected by [`RunOptions::strip_policy`] once a cover is
+    /// extracted.
+    pub strip_extracted: bool,
+    /// Leave files whose embedded front cover meets the policy alone in per-file mode, without
+    /// asking for a cover. `None` processes every file. Setting it in album folder or extract mode
+    /// is an error.
+    pub skip_existing: Option<SkipExisting>,
+    /// Ask for a cover for every file in per-file mode instead of reusing the pick of another
+    /// track of the same album, e.g. for compilations.
+    pub ask_per_file: bool,
+    /// Cover URLs of a pick tried in order until one downloads. Empty uses
+    /// [`CoverUrlKind::DEFAULT_ORDER`].
+    pub cover_url_order: Vec<CoverUrlKind>,
+    /// Smallest acceptable width and height in pixels; smaller downloads fall through to the next
+    /// cover URL.
+    pub min_cover_size: Option<u32>,
+    /// Tags of each file the cover is embedded into in per-file mode.
+    pub tag_target: TagTarget,
+    /// Embedded pictures removed from the files of a folder once its cover is saved in album
+    /// folder mode.
+    pub strip_policy: StripPolicy,
+    /// How long the user has to pick a cover in covit before the file is skipped. `None` waits
+    /// forever.
+    pub pick_timeout: Option<Duration>,
+    /// Pick covers without asking, using the best candidate of the cover source if it scores at
+    /// least the threshold. Items below it are deferred. `None` uses the source's pick. Needs a
+    /// [`cover_source`](Self::cover_source) that is not
+    /// [interactive](crate::source::CoverSource::is_interactive), such as the Cover Art Archive.
+    pub auto_pick: Option<RankingRules>,
+    /// Only build a [`Plan`](crate::plan::Plan) of the changes; no covit calls and no writes.
+    pub dry_run: bool,
+    /// Only build a [`Scan`](crate::scan::Scan) of the artwork of every directory; no covit calls
+    /// and no writes.
+    pub scan: bool,
+    /// Number of workers downloading and embedding covers. `None` uses the available parallelism.
+    pub jobs: Option<usize>,
+    /// Progress journal recording finished directories, files and picks.
+    pub journal: Option<PathBuf>,
+    /// Resume from the entries already in [`RunOptions::journal`] instead of starting over.
+    pub resume: bool,
+    /// Directory below which a backup session saves the original pictures before any write.
+    pub backup_dir: Option<PathBuf>,
+    /// Sources and country covit searches, for the whole run and per directory.
+    pub query: QueryConfig,
+    /// covit binary to run instead of downloading it, e.g. a fake covit in tests.
+    pub covit_path: Option<PathBuf>,
+    /// Directory holding the ffmpeg and ffprobe binaries to run instead of downloading them, with
+    /// the `depend-on-ffmpeg` feature.
+    pub ffmpeg_dir: Option<PathBuf>,
+    /// Receives progress events. `None` runs silently.
+    #[serde(skip)]
+    pub observer: Option<Arc<dyn RunObserver>>,
+    /// Token checked between covit invocations, downloads and file writes. `None` never cancels.
+    #[serde(skip)]
+    pub cancellation_token: Option<CancellationToken>,
+    /// Source asked for covers. `None` downloads covit if needed and uses a
+    /// [`CovitSource`](crate::source::CovitSource).
+    #[serde(skip)]
+    pub cover_source: Option<Arc<dyn CoverSource>>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            input: ".".to_string(),
+            cov_address: None,
+            convert_png_to_jpg: false,
+            jpeg_optimise: None,
+            png_opt: false,
+            album_folder_mode: None,
+            extract: None,
+            strip_extracted: false,
+            skip_existing: None,
+            ask_per_file: false,
+            cover_url_order: Vec::new(),
+            min_cover_size: None,
+            tag_target: TagTarget::default(),
+            strip_policy: StripPolicy::default(),
+            pick_timeout: None,
+            auto_pick: None,
+            dry_run: false,
+            scan: false,
+            jobs: None,
+            journal: None,
+            resume: false,
+            backup_dir: None,
+            query: QueryConfig::default(),
+            covit_path: None,
+            ffmpeg_dir: None,
+            observer: None,
+            cancellation_token: None,
+            cover_source: None,
+        }
+    }
+}
+
+impl fmt::Debug for RunOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunOptions")
+            .field("input", &self.input)
+            .field("cov_address", &self.cov_address)
+            .field("convert_png_to_jpg", &self.convert_png_to_jpg)
+            .field("jpeg_optimise", &self.jpeg_optimise)
+            .field("png_opt", &self.png_opt)
+            .field("album_folder_mode", &self.album_folder_mode)
+            .field("extract", &self.extract)
+            .field("strip_extracted", &self.strip_extracted)
+            .field("skip_existing", &self.skip_existing)
+            .field("ask_per_file", &self.ask_per_file)
+            .field("cover_url_order", &self.cover_url_order)
+            .field("min_cover_size", &self.min_cover_size)
+            .field("tag_target", &self.tag_target)
+            .field("strip_policy", &self.strip_policy)
+            .field("pick_timeout", &self.pick_timeout)
+            .field("auto_pick", &self.auto_pick)
+            .field("dry_run", &self.dry_run)
+            .field("scan", &self.scan)
+            .field("jobs", &self.jobs)
+            .field("journal", &self.journal)
+            .field("resume", &self.resume)
+            .field("backup_dir", &self.backup_dir)
+            .field("query", &self.query)
+            .field("covit_path", &self.covit_path)
+            .field("ffmpeg_dir", &self.ffmpeg_dir)
+            .field("cancellation_token", &self.cancellation_token)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RunOptions {
+    /// Returns a builder initialised with the default options.
+    pub fn builder() -> RunOptionsBuilder {
+        RunOptionsBuilder::default()
+    }
+
+    /// Returns the COV address to use, falling back to [`DEFAULT_COV_ADDRESS`].
+    pub fn cov_address(&self) -> &str {
+        self.cov_address.as_deref().unwrap_or(DEFAULT_COV_ADDRESS)
+    }
+
+    /// Returns the number of workers to use, at least one.
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())).max(1)
+    }
+
+    /// Returns the cover URLs to try in order, falling back to [`CoverUrlKind::DEFAULT_ORDER`].
+    pub fn cover_url_order(&self) -> Vec<CoverUrlKind> {
+        if self.cover_url_order.is_empty() { CoverUrlKind::DEFAULT_ORDER.to_vec() } else { self.cover_url_order.clone() }
+    }
+
+    /// Returns the configured observer, or a [`NoopObserver`] when none is set.
+    pub fn observer(&self) -> Arc<dyn RunObserver> {
+        self.observer.clone().unwrap_or_else(|| Arc::new(NoopObserver))
+    }
+
+    /// Returns the configured cancellation token, or a fresh token that is never cancelled.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation_token.clone().unwrap_or_default()
+    }
+}
+
+/// Builder for [`RunOptions`].
+///
+/// # Example
+///
+/// ```
+/// use rusty_cov::options::RunOptions;
+///
+/// let options = RunOptions::builder().input("music").album_folder_mode("cover").png_opt(true).build();
+/// assert_eq!(options.album_folder_mode.as_deref(), Some("cover"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RunOptionsBuilder {
+    options: RunOptions,
+}
+
+impl RunOptionsBuilder {
+    /// Sets the input directory or file to process.
+    pub fn input<S: Into<String>>(mut self, input: S) -> Self {
+        self.options.input = input.into();
+        self
+    }
+
+    /// Sets the address of the COV website for launch.
+    pub fn cov_address<S: Into<String>>(mut self, cov_address: S) -> Self {
+        self.options.cov_address = Some(cov_address.into());
+        self
+    }
+
+    /// Sets whether PNG images are converted to JPEG before embedding.
+    pub fn convert_png_to_jpg(mut self, convert_png_to_jpg: bool) -> Self {
+        self.options.convert_png_to_jpg = convert_png_to_jpg;
+        self
+    }
+
+    /// Sets the JPEG optimisation quality (1-100), or `None` to disable optimisation.
+    pub fn jpeg_optimise(mut self, jpeg_optimise: Option<u8>) -> Self {
+        self.options.jpeg_optimise = jpeg_optimise;
+        self
+    }
+
+    /// Sets whether PNG images are optimised.
+    pub fn png_opt(mut self, png_opt: bool) -> Self {
+        self.options.png_opt = png_opt;
+        self
+    }
+
+    /// Enables album folder mode, writing the cover as `<cover_name>.<ext>` in each folder.
+    pub fn album_folder_mode<S: Into<String>>(mut self, cover_name: S) -> Self {
+        self.options.album_folder_mode = Some(cover_name.into());
+        self
+    }
+
+    /// Enables extract mode, writing the best embedded front cover of each folder as
+    /// `<cover_name>.<ext>` without looking covers up.
+    pub fn extract<S: Into<String>>(mut self, cover_name: S) -> Self {
+        self.options.extract = Some(cover_name.into());
+        self
+    }
+
+    /// Sets whether extract mode removes the embedded art once the cover file is written.
+    pub fn strip_extracted(mut self, strip_extracted: bool) -> Self {
+        self.options.strip_extracted = strip_extracted;
+        self
+    }
+
+    /// Skips files whose embedded front cover meets `policy` in per-file mode, e.g.
+    /// [`SkipExisting::MinSize`].
+    pub fn skip_existing(mut self, policy: SkipExisting) -> Self {
+        self.options.skip_existing = Some(policy);
+        self
+    }
+
+    /// Sets whether per-file mode asks for every file instead of reusing the cover picked for
+    /// another track of the same album.
+    pub fn ask_per_file(mut self, ask_per_file: bool) -> Self {
+        self.options.ask_per_file = ask_per_file;
+        self
+    }
+
+    /// Sets the cover URLs tried in order until one downloads, e.g. `[Big, Original, Small]`.
+    pub fn cover_url_order(mut self, order: Vec<CoverUrlKind>) -> Self {
+        self.options.cover_url_order = order;
+        self
+    }
+
+    /// Sets the smallest acceptable cover width and height in pixels.
+    pub fn min_cover_size(mut self, min_cover_size: Option<u32>) -> Self {
+        self.options.min_cover_size = min_cover_size;
+        self
+    }
+
+    /// Sets the tags of each file the cover is embedded into, e.g. [`TagTarget::AllPictureTags`].
+    pub fn tag_target(mut self, tag_target: TagTarget) -> Self {
+        self.options.tag_target = tag_target;
+        self
+    }
+
+    /// Sets which embedded pictures are removed in album folder mode, e.g. [`StripPolicy::All`].
+    pub fn strip_policy(mut self, strip_policy: StripPolicy) -> Self {
+        self.options.strip_policy = strip_policy;
+        self
+    }
+
+    /// Sets how long the user has to pick a cover in covit before the file is skipped.
+    pub fn pick_timeout(mut self, pick_timeout: Option<Duration>) -> Self {
+        self.options.pick_timeout = pick_timeout;
+        self
+    }
+
+    /// Picks covers automatically with `rules`, deferring items whose best candidate scores below
+    /// the threshold. The run fails unless a non-interactive [`cover_source`](Self::cover_source)
+    /// is set as well.
+    pub fn auto_pick(mut self, rules: RankingRules) -> Self {
+        self.options.auto_pick = Some(rules);
+        self
+    }
+
+    /// Sets whether to only plan the changes without invoking covit or writing files.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.options.dry_run = dry_run;
+        self
+    }
+
+    /// Sets whether to only report the artwork status of every directory without invoking covit
+    /// or writing files.
+    pub fn scan(mut self, scan: bool) -> Self {
+        self.options.scan = scan;
+        self
+    }
+
+    /// Sets the number of workers downloading and embedding covers.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.options.jobs = Some(jobs);
+        self
+    }
+
+    /// Keeps a progress journal at `path`, truncating any existing journal.
+    pub fn journal<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.options.journal = Some(path.into());
+        self.options.resume = false;
+        self
+    }
+
+    /// Resumes the run recorded in the journal at `path`, appending new entries to it.
+    pub fn resume<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.options.journal = Some(path.into());
+        self.options.resume = true;
+        self
+    }
+
+    /// Backs up the original pictures of every modified file to a new session below `root`, so
+    /// the run can be reverted with [`undo`](crate::backup::undo).
+    pub fn backup_dir<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.options.backup_dir = Some(root.into());
+        self
+    }
+
+    /// Sets the sources covit searches for the whole run.
+    pub fn query_sources(mut self, sources: Vec<QuerySource>) -> Self {
+        self.options.query.sources = Some(sources);
+        self
+    }
+
+    /// Sets the country storefront covit searches for the whole run.
+    pub fn query_country(mut self, country: Country) -> Self {
+        self.options.query.country = Some(country);
+        self
+    }
+
+    /// Adds a pattern extracting the query from file and directory names, see
+    /// [`NamePattern`](crate::name_pattern::NamePattern). Patterns are tried in the order added.
+    pub fn name_pattern(mut self, pattern: NamePattern) -> Self {
+        self.options.query.name_patterns.push(pattern);
+        self
+    }
+
+    /// Adds sources and/or a country used for every file in and below a directory.
+    pub fn query_override(mut self, query_override: QueryOverride) -> Self {
+        self.options.query.overrides.push(query_override);
+        self
+    }
+
+    /// Sets the covit binary to run instead of downloading it.
+    pub fn covit_path<P: Into<PathBuf>>(mut self, covit_path: P) -> Self {
+        self.options.covit_path = Some(covit_path.into());
+        self
+    }
+
+    /// Sets the directory holding the ffmpeg and ffprobe binaries to run instead of downloading
+    /// them.
+    pub fn ffmpeg_dir<P: Into<PathBuf>>(mut self, ffmpeg_dir: P) -> Self {
+        self.options.ffmpeg_dir = Some(ffmpeg_dir.into());
+        self
+    }
+
+    /// Sets the observer that receives progress events, e.g.
+    /// [`TerminalObserver`](crate::observer::TerminalObserver).
+    pub fn observer(mut self, observer: Arc<dyn RunObserver>) -> Self {
+        self.options.observer = Some(observer);
+        self
+    }
+
+    /// Sets the token used to cancel the run, e.g. from a Ctrl-C handler or a Stop button.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.options.cancellation_token = Some(token);
+        self
+    }
+
+    /// Sets the source asked for covers instead of covit, e.g. an internal catalogue or a fake
+    /// source in tests.
+    pub fn cover_source(mut self, cover_source: Arc<dyn CoverSource>) -> Self {
+        self.options.cover_source = Some(cover_source);
+        self
+    }
+
+    /// Consumes the builder and returns the configured [`RunOptions`].
+    pub fn build(self) -> RunOptions {
+        self.options
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::helpers::find_sidecar;
+use crate::lofty::{SkipExisting, embedded_picture_stats};
+
+/// What a run would do to a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// A cover would be embedded into the file (per-file mode).
+    Embed,
+    /// Embedded art would be removed from the file (album folder mode, or extract mode with
+    /// stripping).
+    Strip,
+    /// The file's embedded front cover would be a candidate for the folder's cover file and its
+    /// art left in place (extract mode).
+    Extract,
+    /// The file would be left untouched because its folder already has a cover file, or because
+    /// its embedded front cover meets the skip policy (per-file mode).
+    Skip,
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self {
+            PlannedAction::Embed => "embed",
+            PlannedAction::Strip => "strip",
+            PlannedAction::Extract => "extract",
+            PlannedAction::Skip => "skip",
+        };
+        f.write_str(action)
+    }
+}
+
+/// Planned change for a single file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilePlan {
+    pub path: PathBuf,
+    pub action: PlannedAction,
+    /// Number of pictures currently embedded across all tags.
+    pub picture_count: usize,
+    /// Total size in bytes of the pictures currently embedded across all tags.
+    pub picture_bytes: u64,
+    /// Set when the file's tags could not be read.
+    pub error: Option<String>,
+}
+
+/// Planned changes for a single directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryPlan {
+    pub dir: PathBuf,
+    /// Existing cover file in album folder or extract mode, which causes the directory to be
+    /// skipped.
+    pub sidecar: Option<PathBuf>,
+    pub files: Vec<FilePlan>,
+}
+
+/// Kind of run a [`Plan`] is built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanMode<'a> {
+    /// A cover is embedded into each file, except files whose front cover meets `skip_existing`.
+    PerFile { skip_existing: Option<SkipExisting> },
+    /// A cover file named `cover_name` is downloaded into each directory and the files are
+    /// stripped.
+    AlbumFolder { cover_name: &'a str },
+    /// A cover file named `cover_name` is extracted from the embedded art of each directory, and
+    /// the files are stripped if `strip` is set.
+    Extract { cover_name: &'a str, strip: bool },
+}
+
+impl PlanMode<'_> {
+    /// Returns whether the run writes a cover file into each directory.
+    pub fn writes_cover_file(&self) -> bool {
+        !matches!(self, PlanMode::PerFile { .. })
+    }
+}
+
+/// Every change a run would make, produced without invoking covit or writing any file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Plan {
+    /// Whether a cover file would be written into each directory (album folder or extract mode).
+    pub album_mode: bool,
+    pub directories: Vec<DirectoryPlan>,
+}
+
+impl Plan {
+    /// Builds the plan for `files_by_dir`, as gathered by
+    /// [`RustyCov::populate_from_input`](crate::structs::RustyCov::populate_from_input).
+    ///
+    /// # Arguments
+    ///
+    /// * `files_by_dir` - Files to process grouped by their parent directory.
+    /// * `mode` - Kind of run to plan.
+    pub fn from_files(files_by_dir: &HashMap<PathBuf, Vec<PathBuf>>, mode: PlanMode) -> Self {
+        let mut directories: Vec<DirectoryPlan> = files_by_dir
+            .iter()
+            .map(|(dir, files)| {
+                let sidecar = match mode {
+                    PlanMode::PerFile { .. } => None,
+                    PlanMode::AlbumFolder { cover_name } | PlanMode::Extract { cover_name, .. } => find_sidecar(dir, cover_name),
+                };
+                let action = match (mode, &sidecar) {
+                    (PlanMode::PerFile { .. }, _) => PlannedAction::Embed,
+                    (_, Some(_)) => PlannedAction::Skip,
+                    (PlanMode::AlbumFolder { .. } | PlanMode::Extract { strip: true, .. }, None) => PlannedAction::Strip,
+                    (PlanMode::Extract { strip: false, .. }, None) => PlannedAction::Extract,
+                };
+                let files = files
+                    .iter()
+                    .map(|path| match mode {
+                        // Unreadable files are embedded as usual, as in a run
+                        PlanMode::PerFile { skip_existing: Some(policy) } if policy.is_met_by(path).unwrap_or(false) => {
+                            plan_file(path, PlannedAction::Skip)
+                        }
+                        _ => plan_file(path, action),
+                    })
+                    .collect();
+                DirectoryPlan { dir: dir.clone(), sidecar, files }
+            })
+            .collect();
+        directories.sort_by(|a, b| a.dir.cmp(&b.dir));
+
+        Plan { album_mode: mode.writes_cover_file(), directories }
+    }
+}
+
+fn plan_file(path: &Path, action: PlannedAction) -> FilePlan {
+    match embedded_picture_stats(path) {
+        Ok((picture_count, picture_bytes)) => {
+            FilePlan { path: path.to_path_buf(), action, picture_count, picture_bytes, error: None }
+        }
+        Err(e) => FilePlan { path: path.to_path_buf(), action, picture_count: 0, picture_bytes: 0, error: Some(e.to_string()) },
+    }
+}
+
+impl fmt::Display for Plan {
+    /// Renders the plan as a table per directory.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for dir in &self.directories {
+            match &dir.sidecar {
+                Some(sidecar) => writeln!(f, "Folder: {:?} (cover exists at {:?})", dir.dir, sidecar)?,
+                None if self.album_mode => writeln!(f, "Folder: {:?} (cover would be written)", dir.dir)?,
+                None => writeln!(f, "Folder: {:?}", dir.dir)?,
+            }
+            writeln!(f, "  {:<7} {:>8} {:>12}  FILE", "ACTION", "PICTURES", "BYTES")?;
+            for file in &dir.files {
+                let name = file.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                write!(f, "  {:<7} {:>8} {:>12}  {}", file.action, file.picture_count, file.picture_bytes, name)?;
+                match &file.error {
+                    Some(error) => writeln!(f, " ({})", error)?,
+                    None => writeln!(f)?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        let files: Vec<&FilePlan> = self.directories.iter().flat_map(|dir| &dir.files).collect();
+        let count = |action| files.iter().filter(|file| file.action == action).count();
+        write!(
+            f,
+            "Total: {} folder(s), {} file(s) to embed, {} file(s) to strip, {} file(s) to extract from, {} file(s) skipped.",
+            self.directories.len(),
+            count(PlannedAction::Embed),
+            count(PlannedAction::Strip),
+            count(PlannedAction::Extract),
+            count(PlannedAction::Skip)
+        )
+    }
+}
@@ -0,0 +1,148 @@
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Bounded pool of workers running cover jobs.
+///
+/// At most `2 * workers` jobs are queued or running at once: [`WorkerPool::submit`] blocks until a
+/// slot frees up, which keeps the main thread from racing ahead of the downloads. Results are
+/// returned by [`WorkerPool::join`] in submission order. Uses a rayon thread pool when the
+/// `parallel` feature is enabled and a small std thread pool otherwise.
+pub(crate) struct WorkerPool<T: Send + 'static> {
+    backend: Backend,
+    capacity: usize,
+    next_id: usize,
+    in_flight: usize,
+    sender: Sender<(usize, thread::Result<T>)>,
+    receiver: Receiver<(usize, thread::Result<T>)>,
+    finished: Vec<(usize, thread::Result<T>)>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Creates a pool with `workers` threads (at least one).
+    pub(crate) fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        let (sender, receiver) = channel();
+        Self {
+            backend: Backend::new(workers),
+            capacity: workers * 2,
+            next_id: 0,
+            in_flight: 0,
+            sender,
+            receiver,
+            finished: Vec::new(),
+        }
+    }
+
+    /// Queues `job`, blocking while the pool is at capacity.
+    pub(crate) fn submit<F: FnOnce() -> T + Send + 'static>(&mut self, job: F) {
+        while self.in_flight >= self.capacity {
+            self.wait_for_one();
+        }
+
+        let id = self.next_id;
+        let sender = self.sender.clone();
+        self.next_id += 1;
+        self.in_flight += 1;
+        self.backend.execute(Box::new(move || {
+            let result = catch_unwind(AssertUnwindSafe(job));
+            let _ = sender.send((id, result));
+        }));
+    }
+
+    /// Waits for every queued job and returns their results in submission order. A job that
+    /// panicked yields `Err` with the panic payload.
+    pub(crate) fn join(mut self) -> Vec<thread::Result<T>> {
+        while self.in_flight > 0 {
+            self.wait_for_one();
+        }
+        self.backend.shutdown();
+
+        let mut finished = std::mem::take(&mut self.finished);
+        finished.sort_by_key(|(id, _)| *id);
+        finished.into_iter().map(|(_, result)| result).collect()
+    }
+
+    fn wait_for_one(&mut self) {
+        // The pool keeps its own sender alive, so this only fails if a worker thread died
+        // without reporting, which `catch_unwind` prevents.
+        let result = self.receiver.recv().expect("Worker pool channel closed unexpectedly");
+        self.finished.push(result);
+        self.in_flight -= 1;
+    }
+}
+
+#[cfg(feature = "parallel")]
+struct Backend {
+    pool: rayon::ThreadPool,
+}
+
+#[cfg(feature = "parallel")]
+impl Backend {
+    fn new(workers: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|i| format!("rusty_cov-worker-{}", i))
+            .build()
+            .expect("Failed to build worker pool");
+        Self { pool }
+    }
+
+    fn execute(&self, job: Job) {
+        self.pool.spawn(job);
+    }
+
+    fn shutdown(&mut self) {}
+}
+
+#[cfg(not(feature = "parallel"))]
+struct Backend {
+    jobs: Option<std::sync::mpsc::SyncSender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+#[cfg(not(feature = "parallel"))]
+impl Backend {
+    fn new(workers: usize) -> Self {
+        use std::sync::{Arc, Mutex};
+
+        let (jobs, queue) = std::sync::mpsc::sync_channel::<Job>(workers);
+        let queue = Arc::new(Mutex::new(queue));
+        let workers = (0..workers)
+            .map(|i| {
+                let queue = Arc::clone(&queue);
+                thread::Builder::new()
+                    .name(format!("rusty_cov-worker-{}", i))
+                    .spawn(move || {
+                        loop {
+                            // Release the lock before running the job so other workers can
+                            // pick up the next one.
+                            let job = queue.lock().unwrap().recv();
+                            match job {
+                                Ok(job) => job(),
+                                Err(_) => break,
+                            }
+                        }
+                    })
+                    .expect("Failed to spawn worker thread")
+            })
+            .collect();
+        Self { jobs: Some(jobs), workers }
+    }
+
+    fn execute(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send(job).expect("Worker pool has shut down");
+        }
+    }
+
+    fn shutdown(&mut self) {
+        // Dropping the sender ends each worker's receive loop.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::name_pattern::NamePattern;
+
+/// Store searched by covit, passed as `--query-sources`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuerySource {
+    Amazon,
+    AmazonMusic,
+    AppleMusic,
+    Bandcamp,
+    Beatport,
+    Booth,
+    Deezer,
+    Discogs,
+    FanartTv,
+    ITunes,
+    LastFm,
+    MusicBrainz,
+    Qobuz,
+    SoundCloud,
+    Spotify,
+    Tidal,
+    VgmDb,
+}
+
+impl QuerySource {
+    /// Every known source, in the order they are listed in help texts.
+    pub const ALL: [QuerySource; 17] = [
+        QuerySource::Amazon,
+        QuerySource::AmazonMusic,
+        QuerySource::AppleMusic,
+        QuerySource::Bandcamp,
+        QuerySource::Beatport,
+        QuerySource::Booth,
+        QuerySource::Deezer,
+        QuerySource::Discogs,
+        QuerySource::FanartTv,
+        QuerySource::ITunes,
+        QuerySource::LastFm,
+        QuerySource::MusicBrainz,
+        QuerySource::Qobuz,
+        QuerySource::SoundCloud,
+        QuerySource::Spotify,
+        QuerySource::Tidal,
+        QuerySource::VgmDb,
+    ];
+
+    /// Sources queried when none are configured.
+    pub const DEFAULT: [QuerySource; 9] = [
+        QuerySource::Booth,
+        QuerySource::AmazonMusic,
+        QuerySource::AppleMusic,
+        QuerySource::MusicBrainz,
+        QuerySource::Discogs,
+        QuerySource::FanartTv,
+        QuerySource::SoundCloud,
+        QuerySource::ITunes,
+        QuerySource::Tidal,
+    ];
+
+    /// Returns the identifier covit expects for this source.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuerySource::Amazon => "amazon",
+            QuerySource::AmazonMusic => "amazonmusic",
+            QuerySource::AppleMusic => "applemusic",
+            QuerySource::Bandcamp => "bandcamp",
+            QuerySource::Beatport => "beatport",
+            QuerySource::Booth => "booth",
+            QuerySource::Deezer => "deezer",
+            QuerySource::Discogs => "discogs",
+            QuerySource::FanartTv => "fanarttv",
+            QuerySource::ITunes => "itunes",
+            QuerySource::LastFm => "lastfm",
+            QuerySource::MusicBrainz => "musicbrainz",
+            QuerySource::Qobuz => "qobuz",
+            QuerySource::SoundCloud => "soundcloud",
+            QuerySource::Spotify => "spotify",
+            QuerySource::Tidal => "tidal",
+            QuerySource::VgmDb => "vgmdb",
+        }
+    }
+
+    /// Parses a comma separated list of sources such as `"musicbrainz,discogs"`.
+    pub fn parse_list(list: &str) -> Result<Vec<QuerySource>, QueryOptionError> {
+        list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(QuerySource::from_str).collect()
+    }
+}
+
+impl fmt::Display for QuerySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for QuerySource {
+    type Err = QueryOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QuerySource::ALL
+            .into_iter()
+            .find(|source| source.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| QueryOptionError::UnknownSource(s.trim().to_string()))
+    }
+}
+
+/// Two-letter country code selecting the storefront covit searches, passed as `--query-country`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Country(String);
+
+impl Country {
+    /// Storefront used when no country is configured.
+    pub const DEFAULT: &str = "gb";
+
+    /// Returns the lowercase country code.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Country {
+    fn default() -> Self {
+        Self(Self::DEFAULT.to_string())
+    }
+}
+
+impl fmt::Display for Country {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Country {
+    type Err = QueryOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim();
+        if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+            Ok(Self(code.to_ascii_lowercase()))
+        } else {
+            Err(QueryOptionError::InvalidCountry(code.to_string()))
+        }
+    }
+}
+
+impl TryFrom<String> for Country {
+    type Error = QueryOptionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Country> for String {
+    fn from(country: Country) -> Self {
+        country.0
+    }
+}
+
+/// Errors returned when parsing query sources, countries and overrides.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum QueryOptionError {
+    #[error("Unknown query source {0:?}, expected one of: {known}", known = QuerySource::ALL.map(|s| s.as_str()).join(", "))]
+    UnknownSource(String),
+    #[error("Invalid query country {0:?}, expected a two-letter country code such as \"gb\"")]
+    InvalidCountry(String),
+    #[error("Invalid query override {0:?}, expected DIR=SOURCES, DIR=@COUNTRY or DIR=SOURCES@COUNTRY")]
+    InvalidOverride(String),
+}
+
+/// Sources and country used for a single covit invocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuerySettings {
+    pub sources: Vec<QuerySource>,
+    pub country: Country,
+    /// Patterns tried on the file name before the built-in ones, see [`NamePattern`].
+    pub name_patterns: Vec<NamePattern>,
+}
+
+impl Default for QuerySettings {
+    fn default() -> Self {
+        Self { sources: QuerySource::DEFAULT.to_vec(), country: Country::default(), name_patterns: Vec::new() }
+    }
+}
+
+impl QuerySettings {
+    /// Returns the sources joined for `--query-sources`.
+    pub fn sources_arg(&self) -> String {
+        self.sources.iter().map(QuerySource::as_str).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Sources and/or country used for every file in and below `dir`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryOverride {
+    pub dir: PathBuf,
+    pub sources: Option<Vec<QuerySource>>,
+    pub country: Option<Country>,
+}
+
+impl FromStr for QueryOverride {
+    type Err = QueryOptionError;
+
+    /// Parses `DIR=SOURCES`, `DIR=@COUNTRY` or `DIR=SOURCES@COUNTRY`, e.g.
+    /// `Classical=musicbrainz,discogs@us`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || QueryOptionError::InvalidOverride(s.to_string());
+        let (dir, settings) = s.rsplit_once('=').ok_or_else(invalid)?;
+        let (sources, country) = match settings.split_once('@') {
+            Some((sources, country)) => (sources, Some(country.parse()?)),
+            None => (settings, None),
+        };
+        let sources = QuerySource::parse_list(sources)?;
+        if dir.is_empty() || (sources.is_empty() && country.is_none()) {
+            return Err(invalid());
+        }
+
+        Ok(Self { dir: PathBuf::from(dir), sources: (!sources.is_empty()).then_some(sources), country })
+    }
+}
+
+/// Query settings of a run: run-wide sources and country plus per-directory overrides.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryConfig {
+    /// Sources for the whole run. `None` or an empty list uses [`QuerySource::DEFAULT`].
+    pub sources: Option<Vec<QuerySource>>,
+    /// Country for the whole run. `None` uses [`Country::DEFAULT`].
+    pub country: Option<Country>,
+    /// Overrides for individual directories. The deepest directory containing a file wins.
+    /// Relative directories are resolved by [`QueryConfig::resolved`].
+    pub overrides: Vec<QueryOverride>,
+    /// Patterns extracting the query from file and directory names when the tags are not enough,
+    /// tried in order before the built-in ones.
+    pub name_patterns: Vec<NamePattern>,
+}
+
+impl QueryConfig {
+    /// Returns the config with the override directories canonicalized so that they match the
+    /// files of a run however either is spelled. Relative directories are taken from
+    /// `input_root`, or from the current directory if they do not exist there.
+    pub fn resolved(&self, input_root: &Path) -> QueryConfig {
+        let mut config = self.clone();
+        for query_override in &mut config.overrides {
+            let in_input = input_root.join(&query_override.dir);
+            if query_override.dir.is_relative() && in_input.exists() {
+                query_override.dir = in_input;
+            }
+            query_override.dir = normalise(&query_override.dir);
+        }
+        config
+    }
+
+    /// Returns the overrides whose directory contains none of `paths`.
+    pub fn unmatched_overrides<'p>(&self, paths: impl IntoIterator<Item = &'p Path>) -> Vec<&QueryOverride> {
+        let paths: Vec<PathBuf> = paths.into_iter().map(normalise).collect();
+        self.overrides
+            .iter()
+            .filter(|query_override| {
+                let dir = normalise(&query_override.dir);
+                !paths.iter().any(|path| path.starts_with(&dir))
+            })
+            .collect()
+    }
+
+    /// Resolves the settings for the file or directory at `path`.
+    pub fn settings_for(&self, path: &Path) -> QuerySettings {
+        let mut settings = QuerySettings { name_patterns: self.name_patterns.clone(), ..QuerySettings::default() };
+        if let Some(sources) = self.sources.as_ref().filter(|sources| !sources.is_empty()) {
+            settings.sources = sources.clone();
+        }
+        if let Some(country) = &self.country {
+            settings.country = country.clone();
+        }
+
+        let path = normalise(path);
+        let most_specific = self
+            .overrides
+            .iter()
+            .filter(|query_override| path.starts_with(normalise(&query_override.dir)))
+            .max_by_key(|query_override| query_override.dir.components().count());
+        if let Some(query_override) = most_specific {
+            if let Some(sources) = &query_override.sources {
+                settings.sources = sources.clone();
+            }
+            if let Some(country) = &query_override.country {
+                settings.country = country.clone();
+            }
+        }
+
+        settings
+    }
+}
+
+/// Canonicalizes `path` if it exists, otherwise drops its `.` components so that
+/// `./Classical/x.flac` still matches an override for `Classical`.
+fn normalise(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.components().filter(|component| *component != Component::CurDir).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_query_sources() {
+        assert_eq!("MusicBrainz".parse(), Ok(QuerySource::MusicBrainz));
+        assert_eq!(" itunes ".parse(), Ok(QuerySource::ITunes));
+        assert_eq!("napster".parse::<QuerySource>(), Err(QueryOptionError::UnknownSource("napster".to_string())));
+        assert_eq!(QuerySource::parse_list("deezer, tidal,"), Ok(vec![QuerySource::Deezer, QuerySource::Tidal]));
+        assert!(QuerySource::parse_list("deezer,nope").is_err());
+    }
+
+    #[test]
+    fn parses_countries() {
+        assert_eq!("US".parse::<Country>().map(String::from), Ok("us".to_string()));
+        assert_eq!("usa".parse::<Country>(), Err(QueryOptionError::InvalidCountry("usa".to_string())));
+        assert!("u1".parse::<Country>().is_err());
+        assert!("".parse::<Country>().is_err());
+    }
+
+    #[test]
+    fn parses_query_overrides() {
+        let query_override: QueryOverride = "Classical=musicbrainz,discogs@us".parse().unwrap();
+        assert_eq!(query_override.dir, PathBuf::from("Classical"));
+        assert_eq!(query_override.sources, Some(vec![QuerySource::MusicBrainz, QuerySource::Discogs]));
+        assert_eq!(query_override.country, Some("us".parse().unwrap()));
+
+        let query_override: QueryOverride = "a=b=@jp".parse().unwrap();
+        assert_eq!(query_override.dir, PathBuf::from("a=b"));
+        assert_eq!(query_override.sources, None);
+
+        for invalid in ["Classical", "=deezer", "Classical=", "Classical=@", "Classical=deezer@usa", "Classical=nope"] {
+            assert!(invalid.parse::<QueryOverride>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn most_specific_override_wins() {
+        let config = QueryConfig {
+            sources: Some(vec![QuerySource::Deezer]),
+            country: Some("fr".parse().unwrap()),
+            overrides: vec![
+                "no-such-music/Classical=musicbrainz@us".parse().unwrap(),
+                "no-such-music/Classical/Opera=@it".parse().unwrap(),
+            ],
+            name_patterns: Vec::new(),
+        };
+
+        // Only the deepest override applies, the run-wide sources fill in what it leaves out
+        let settings = config.settings_for(Path::new("./no-such-music/Classical/Opera/01.flac"));
+        assert_eq!(settings.sources, vec![QuerySource::Deezer]);
+        assert_eq!(settings.country.as_str(), "it");
+
+        let settings = config.settings_for(Path::new("no-such-music/Classical/01.flac"));
+        assert_eq!(settings.sources, vec![QuerySource::MusicBrainz]);
+        assert_eq!(settings.country.as_str(), "us");
+
+        let settings = config.settings_for(Path::new("no-such-music/Classical Extra/01.flac"));
+        assert_eq!(settings.sources, vec![QuerySource::Deezer]);
+        assert_eq!(settings.country.as_str(), "fr");
+    }
+
+    #[test]
+    fn resolves_overrides_against_the_input() {
+        let input = std::env::temp_dir().join(format!("rusty_cov_query_{}", std::process::id()));
+        std::fs::create_dir_all(input.join("Classical")).unwrap();
+        let config = QueryConfig {
+            overrides: vec!["Classical=@us".parse().unwrap(), "Jazz=@us".parse().unwrap()],
+            ..QueryConfig::default()
+        };
+
+        let resolved = config.resolved(&input);
+        let file = input.join("Classical").join("01.flac");
+        assert_eq!(resolved.settings_for(&file).country.as_str(), "us");
+        let unmatched: Vec<&Path> = resolved.unmatched_overrides([file.as_path()]).iter().map(|o| o.dir.as_path()).collect();
+        assert_eq!(unmatched, vec![Path::new("Jazz")]);
+
+        std::fs::remove_dir_all(&input).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use crate::name_pattern::clean_name;
+use crate::source::CoverQuery;
+use crate::structs::Picked;
+
+const ALBUM_WEIGHT: f64 = 0.35;
+const ARTIST_WEIGHT: f64 = 0.25;
+const RESOLUTION_WEIGHT: f64 = 0.15;
+const SQUARENESS_WEIGHT: f64 = 0.1;
+const SOURCE_WEIGHT: f64 = 0.1;
+const DATE_WEIGHT: f64 = 0.05;
+
+/// Score given to a criterion when the tags or the candidate lack the value it needs.
+const NEUTRAL: f64 = 0.5;
+
+/// Rules scoring the candidates of a [`CoverSource`](crate::source::CoverSource) so that a cover
+/// can be picked without asking the user.
+///
+/// Each candidate gets a score between 0 and 1, the weighted sum of:
+///
+/// - 35% album title similarity to the album tag,
+/// - 25% artist similarity to the album artist (or artist) tag,
+/// - 15% resolution, full marks at [`RankingRules::target_size`],
+/// - 10% squareness,
+/// - 10% position in [`RankingRules::preferred_sources`],
+/// - 5% release year match with the year tag.
+///
+/// A criterion that cannot be judged because a tag or the cover info is missing counts half,
+/// except resolution which counts nothing when unknown.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingRules {
+    /// Lowest accepted score. Items whose best candidate scores lower are deferred.
+    pub threshold: f64,
+    /// Width and height at and above which a candidate gets the full resolution score.
+    pub target_size: u32,
+    /// Sources in order of preference, compared case-insensitively with [`Picked::source`]. Empty
+    /// treats every source alike.
+    pub preferred_sources: Vec<String>,
+}
+
+impl Default for RankingRules {
+    fn default() -> Self {
+        Self { threshold: 0.7, target_size: 1000, preferred_sources: Vec::new() }
+    }
+}
+
+/// A candidate along with its score.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoredCandidate {
+    pub picked: Picked,
+    pub score: f64,
+}
+
+impl RankingRules {
+    /// Returns the score of `picked` for `query`, between 0 and 1.
+    pub fn score(&self, query: &CoverQuery, picked: &Picked) -> f64 {
+        let release = &picked.release_info;
+        let cover = &picked.cover_info;
+
+        let album = match (&query.album, &release.title) {
+            (Some(album), Some(title)) => similarity(album, title),
+            (None, _) => NEUTRAL,
+            (Some(_), None) => 0.0,
+        };
+        let artist = match (query.album_artist.as_ref().or(query.artist.as_ref()), &release.artist) {
+            (Some(tag), Some(artist)) => similarity(tag, artist),
+            (None, _) => NEUTRAL,
+            (Some(_), None) => 0.0,
+        };
+        let (resolution, squareness) = match (cover.width, cover.height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => {
+                let (short, long) = (width.min(height) as f64, width.max(height) as f64);
+                ((short / self.target_size.max(1) as f64).min(1.0), short / long)
+            }
+            _ => (0.0, NEUTRAL),
+        };
+        let source = if self.preferred_sources.is_empty() {
+            1.0
+        } else {
+            let position = picked.source.as_ref().and_then(|source| {
+                self.preferred_sources.iter().position(|preferred| preferred.eq_ignore_ascii_case(source))
+            });
+            position.map_or(0.0, |position| 1.0 - position as f64 / self.preferred_sources.len() as f64)
+        };
+        let release_year = release.date.as_deref().and_then(|date| date.get(..4)).and_then(|year| year.parse::<u32>().ok());
+        let date = match (query.year, release_year) {
+            (Some(year), Some(release_year)) => match year.abs_diff(release_year) {
+                0 => 1.0,
+                1 => 0.5,
+                _ => 0.0,
+            },
+            _ => NEUTRAL,
+        };
+
+        ALBUM_WEIGHT * album +
+            ARTIST_WEIGHT * artist +
+            RESOLUTION_WEIGHT * resolution +
+            SQUARENESS_WEIGHT * squareness +
+            SOURCE_WEIGHT * source +
+            DATE_WEIGHT * date
+    }
+
+    /// Scores `candidates` for `query` and sorts them best first, keeping the source's order on
+    /// ties.
+    pub fn rank(&self, query: &CoverQuery, candidates: Vec<Picked>) -> Vec<ScoredCandidate> {
+        let mut scored: Vec<ScoredCandidate> =
+            candidates.into_iter().map(|picked| ScoredCandidate { score: self.score(query, &picked), picked }).collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored
+    }
+}
+
+/// Returns how alike two names are between 0 and 1, comparing the character pairs of their
+/// lowercase alphanumeric forms once bracketed tags such as `(Deluxe Edition)` are removed.
+fn similarity(a: &str, b: &str) -> f64 {
+    let normalise = |name: &str| -> Vec<char> {
+        let cleaned = clean_name(name).to_lowercase();
+        let words: Vec<String> = cleaned.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_string).collect();
+        words.join(" ").chars().collect()
+    };
+    let (a, b) = (normalise(a), normalise(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+    if a.len() < 2 || b.len() < 2 {
+        return 0.0;
+    }
+
+    let mut pairs: Vec<(char, char)> = a.windows(2).map(|pair| (pair[0], pair[1])).collect();
+    let total = pairs.len() + b.len() - 1;
+    let mut shared = 0;
+    for pair in b.windows(2).map(|pair| (pair[0], pair[1])) {
+        if let Some(index) = pairs.iter().position(|seen| *seen == pair) {
+            pairs.swap_remove(index);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{CoverInfo, ReleaseInfo};
+
+    fn candidate(url: &str, title: &str, artist: &str, size: u32) -> Picked {
+        Picked {
+            big_cover_url: url.to_string(),
+            release_info: ReleaseInfo { title: Some(title.to_string()), artist: Some(artist.to_string()), ..ReleaseInfo::default() },
+            cover_info: CoverInfo { width: Some(size), height: Some(size), ..CoverInfo::default() },
+            ..Picked::default()
+        }
+    }
+
+    fn query() -> CoverQuery {
+        CoverQuery { album: Some("Discovery".to_string()), artist: Some("Daft Punk".to_string()), ..CoverQuery::default() }
+    }
+
+    #[test]
+    fn compares_names() {
+        assert_eq!(similarity("Discovery", "discovery"), 1.0);
+        assert_eq!(similarity("Discovery (Deluxe Edition) [FLAC]", "Discovery"), 1.0);
+        assert_eq!(similarity("Homework", "Alive 2007"), 0.0);
+        assert_eq!(similarity("", "Discovery"), 0.0);
+
+        let close = similarity("Random Access Memories", "Random Access Memory");
+        assert!(close > 0.8 && close < 1.0, "{}", close);
+    }
+
+    #[test]
+    fn ranks_best_first() {
+        let rules = RankingRules::default();
+        let candidates = vec![
+            candidate("other", "Homework", "Daft Punk", 1000),
+            candidate("small", "Discovery", "Daft Punk", 300),
+            candidate("best", "Discovery", "Daft Punk", 1200),
+        ];
+
+        let ranked = rules.rank(&query(), candidates);
+        let urls: Vec<&str> = ranked.iter().map(|scored| scored.picked.big_cover_url.as_str()).collect();
+        assert_eq!(urls, ["best", "small", "other"]);
+        assert!(ranked[0].score >= rules.threshold);
+        assert!(ranked[2].score < rules.threshold);
+    }
+
+    #[test]
+    fn keeps_the_source_order_on_ties() {
+        let candidates = vec![candidate("first", "Discovery", "Daft Punk", 1000), candidate("second", "Discovery", "Daft Punk", 1500)];
+
+        let ranked = RankingRules::default().rank(&query(), candidates);
+        assert_eq!(ranked[0].score, ranked[1].score);
+        assert_eq!(ranked[0].picked.big_cover_url, "first");
+    }
+
+    #[test]
+    fn prefers_earlier_sources() {
+        let rules = RankingRules { preferred_sources: vec!["Discogs".to_string(), "itunes".to_string()], ..RankingRules::default() };
+        let mut itunes = candidate("itunes", "Discovery", "Daft Punk", 1000);
+        itunes.source = Some("itunes".to_string());
+        let mut discogs = candidate("discogs", "Discovery", "Daft Punk", 1000);
+        discogs.source = Some("discogs".to_string());
+
+        let ranked = rules.rank(&query(), vec![itunes, discogs]);
+        assert_eq!(ranked[0].picked.big_cover_url, "discogs");
+    }
+}
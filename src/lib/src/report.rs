@@ -0,0 +1,442 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use lofty::tag::TagType;
+use serde::Serialize;
+
+use crate::error::RustyCovError;
+use crate::lofty::tag_type_name;
+use crate::observer::{RunObserver, RunSummary, TargetKind};
+use crate::plan::Plan;
+use crate::scan::Scan;
+use crate::structs::{CoverUrl, Picked};
+
+/// Size in bytes of a cover image as downloaded and after conversion/optimisation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ImageSizes {
+    pub before: u64,
+    pub after: u64,
+}
+
+/// What happened to a directory or file during a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The run ended (e.g. was cancelled) before the item was processed.
+    NotProcessed,
+    /// A cover was embedded into the file.
+    Embedded,
+    /// The file's embedded art was removed (album folder mode).
+    Stripped,
+    /// The file held no embedded art to remove and was left unchanged (album folder mode).
+    Unchanged,
+    /// The folder's cover file was written (album folder mode).
+    SidecarSaved,
+    /// The folder was skipped because its cover file already exists.
+    SkippedSidecarExists,
+    /// A resumed journal records the item as done.
+    AlreadyDone,
+    /// The file already embeds a front cover meeting the skip policy.
+    SkippedExistingArt,
+    /// No cover could be picked.
+    NoCoverFound,
+    /// The user closed the covit tab without picking a cover.
+    UserClosedTab,
+    /// No cover was picked in covit before the pick timeout.
+    PickTimedOut,
+    /// Automatic picking found no candidate scoring above the threshold.
+    Deferred,
+    /// The item failed with the given error.
+    Error { message: String },
+}
+
+/// Outcome of a single file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    /// Cover picked for the file (per-file mode).
+    pub picked: Option<Picked>,
+    /// Score of the automatically picked cover, or of the best candidate if the file was
+    /// deferred (per-file mode).
+    pub score: Option<f64>,
+    /// URL the cover was downloaded from (per-file mode).
+    pub cover_url: Option<CoverUrl>,
+    /// Names of the tags the cover was written to, see [`tag_type_name`].
+    pub tags: Vec<String>,
+    pub image_sizes: Option<ImageSizes>,
+}
+
+/// Outcome of a directory and its files.
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryReport {
+    pub dir: PathBuf,
+    /// Outcome of the folder's cover file (album folder mode). In per-file mode this stays
+    /// [`Outcome::NotProcessed`] and the files carry the outcomes.
+    pub outcome: Outcome,
+    /// Cover picked for the folder (album folder mode).
+    pub picked: Option<Picked>,
+    /// Score of the automatically picked cover, or of the best candidate if the folder was
+    /// deferred (album folder mode).
+    pub score: Option<f64>,
+    /// URL the cover was downloaded from (album folder mode).
+    pub cover_url: Option<CoverUrl>,
+    /// Cover file written or found (album folder mode).
+    pub sidecar: Option<PathBuf>,
+    pub image_sizes: Option<ImageSizes>,
+    pub files: Vec<FileReport>,
+}
+
+/// Per-item outcomes of a run, returned by [`run_with`](crate::run_with).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    pub summary: RunSummary,
+    pub directories: Vec<DirectoryReport>,
+    /// Errors not tied to a file or folder of the run, e.g. failing to write the backup session.
+    pub errors: Vec<String>,
+}
+
+/// Observer that builds a [`RunReport`] while forwarding every event to `inner`.
+pub(crate) struct ReportCollector {
+    inner: Arc<dyn RunObserver>,
+    state: Mutex<ReportState>,
+}
+
+struct ReportState {
+    summary: RunSummary,
+    dirs: BTreeMap<PathBuf, DirectoryReport>,
+    /// Index of each file within its directory's `files`.
+    files: HashMap<PathBuf, (PathBuf, usize)>,
+    /// Name of the cover file written in each directory, without extension.
+    sidecar_name: Option<String>,
+    errors: Vec<String>,
+}
+
+impl ReportState {
+    fn file_mut(&mut self, path: &Path) -> Option<&mut FileReport> {
+        let (dir, index) = self.files.get(path)?;
+        self.dirs.get_mut(dir).map(|dir| &mut dir.files[*index])
+    }
+
+    /// Returns the directory `path` refers to, either directly or as the folder of its cover
+    /// file.
+    fn dir_mut(&mut self, path: &Path) -> Option<&mut DirectoryReport> {
+        if self.dirs.contains_key(path) {
+            return self.dirs.get_mut(path);
+        }
+        let is_sidecar = self.sidecar_name.as_deref().is_some_and(|name| path.file_stem().is_some_and(|stem| stem == name));
+        if !is_sidecar {
+            return None;
+        }
+        self.dirs.get_mut(path.parent()?)
+    }
+
+    /// Sets the outcome of the file or directory `path` refers to, returning `false` if it is not
+    /// part of the run.
+    fn set_outcome(&mut self, path: &Path, outcome: Outcome) -> bool {
+        if let Some(file) = self.file_mut(path) {
+            file.outcome = outcome;
+        } else if let Some(dir) = self.dir_mut(path) {
+            dir.outcome = outcome;
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+impl ReportCollector {
+    /// Creates a collector listing every file in `files_by_dir` as not processed yet. Errors about
+    /// a file named `sidecar_name` in one of the directories are recorded on the directory.
+    pub(crate) fn new(inner: Arc<dyn RunObserver>, files_by_dir: &HashMap<PathBuf, Vec<PathBuf>>, sidecar_name: Option<&str>) -> Self {
+        let mut dirs = BTreeMap::new();
+        let mut files = HashMap::new();
+        for (dir, dir_files) in files_by_dir {
+            let reports = dir_files
+                .iter()
+                .enumerate()
+                .map(|(index, path)| {
+                    files.insert(path.clone(), (dir.clone(), index));
+                    FileReport {
+                        path: path.clone(),
+                        outcome: Outcome::NotProcessed,
+                        picked: None,
+                        score: None,
+                        cover_url: None,
+                        tags: Vec::new(),
+                        image_sizes: None,
+                    }
+                })
+                .collect();
+            dirs.insert(
+                dir.clone(),
+                DirectoryReport {
+                    dir: dir.clone(),
+                    outcome: Outcome::NotProcessed,
+                    picked: None,
+                    score: None,
+                    cover_url: None,
+                    sidecar: None,
+                    image_sizes: None,
+                    files: reports,
+                },
+            );
+        }
+
+        let state = ReportState {
+            summary: RunSummary::default(),
+            dirs,
+            files,
+            sidecar_name: sidecar_name.map(str::to_string),
+            errors: Vec::new(),
+        };
+        Self { inner, state: Mutex::new(state) }
+    }
+
+    /// Returns the report of the events received so far.
+    pub(crate) fn report(&self) -> RunReport {
+        let state = self.state.lock().unwrap();
+        RunReport { summary: state.summary, directories: state.dirs.values().cloned().collect(), errors: state.errors.clone() }
+    }
+}
+
+impl RunObserver for ReportCollector {
+    fn dependency_downloading(&self, name: &str) {
+        self.inner.dependency_downloading(name);
+    }
+
+    fn dependency_extracting(&self, name: &str) {
+        self.inner.dependency_extracting(name);
+    }
+
+    fn plan_ready(&self, plan: &Plan) {
+        self.inner.plan_ready(plan);
+    }
+
+    fn scan_ready(&self, scan: &Scan) {
+        self.inner.scan_ready(scan);
+    }
+
+    fn no_files_found(&self) {
+        self.inner.no_files_found();
+    }
+
+    fn query_override_unused(&self, dir: &Path) {
+        self.inner.query_override_unused(dir);
+    }
+
+    fn backup_session_started(&self, session_dir: &Path) {
+        self.inner.backup_session_started(session_dir);
+    }
+
+    fn folder_started(&self, dir: &Path) {
+        self.inner.folder_started(dir);
+    }
+
+    fn folder_skipped(&self, dir: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(report) = state.dirs.get_mut(dir) {
+            report.outcome = Outcome::SkippedSidecarExists;
+            for file in &mut report.files {
+                file.outcome = Outcome::SkippedSidecarExists;
+            }
+        }
+        drop(state);
+        self.inner.folder_skipped(dir);
+    }
+
+    fn already_done(&self, target: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(report) = state.dirs.get_mut(target) {
+            report.outcome = Outcome::AlreadyDone;
+            for file in &mut report.files {
+                file.outcome = Outcome::AlreadyDone;
+            }
+        } else {
+            state.set_outcome(target, Outcome::AlreadyDone);
+        }
+        drop(state);
+        self.inner.already_done(target);
+    }
+
+    fn existing_art_kept(&self, path: &Path) {
+        self.state.lock().unwrap().set_outcome(path, Outcome::SkippedExistingArt);
+        self.inner.existing_art_kept(path);
+    }
+
+    fn cover_picked(&self, target: &Path, kind: TargetKind, picked: &Picked) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file_mut(target) {
+            file.picked = Some(picked.clone());
+        } else if let Some(dir) = state.dirs.get_mut(target) {
+            dir.picked = Some(picked.clone());
+        }
+        drop(state);
+        self.inner.cover_picked(target, kind, picked);
+    }
+
+    fn no_cover_found(&self, target: &Path, kind: TargetKind) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file_mut(target) {
+            if !matches!(file.outcome, Outcome::UserClosedTab | Outcome::PickTimedOut) {
+                file.outcome = Outcome::NoCoverFound;
+            }
+        } else if let Some(dir) = state.dirs.get_mut(target) {
+            // In album folder mode covit is tried file by file, so a closed tab or a timeout is
+            // recorded on the file it was opened for.
+            let closed = dir.files.iter().any(|file| file.outcome == Outcome::UserClosedTab);
+            let timed_out = dir.files.iter().any(|file| file.outcome == Outcome::PickTimedOut);
+            dir.outcome = match (closed, timed_out) {
+                (true, _) => Outcome::UserClosedTab,
+                (false, true) => Outcome::PickTimedOut,
+                (false, false) => Outcome::NoCoverFound,
+            };
+        }
+        drop(state);
+        self.inner.no_cover_found(target, kind);
+    }
+
+    fn cover_auto_picked(&self, target: &Path, score: f64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file_mut(target) {
+            file.score = Some(score);
+        } else if let Some(dir) = state.dirs.get_mut(target) {
+            dir.score = Some(score);
+        }
+        drop(state);
+        self.inner.cover_auto_picked(target, score);
+    }
+
+    fn cover_deferred(&self, target: &Path, best_score: f64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file_mut(target) {
+            file.outcome = Outcome::Deferred;
+            file.score = Some(best_score);
+        } else if let Some(dir) = state.dirs.get_mut(target) {
+            dir.outcome = Outcome::Deferred;
+            dir.score = Some(best_score);
+        }
+        drop(state);
+        self.inner.cover_deferred(target, best_score);
+    }
+
+    fn covit_listening(&self, target: &Path, address: &str) {
+        self.inner.covit_listening(target, address);
+    }
+
+    fn user_closed_tab(&self, target: &Path) {
+        self.state.lock().unwrap().set_outcome(target, Outcome::UserClosedTab);
+        self.inner.user_closed_tab(target);
+    }
+
+    fn covit_timed_out(&self, target: &Path) {
+        self.state.lock().unwrap().set_outcome(target, Outcome::PickTimedOut);
+        self.inner.covit_timed_out(target);
+    }
+
+    fn download_started(&self, url: &str, total: Option<u64>) {
+        self.inner.download_started(url, total);
+    }
+
+    fn download_progress(&self, url: &str, downloaded: u64, total: Option<u64>) {
+        self.inner.download_progress(url, downloaded, total);
+    }
+
+    fn download_finished(&self, url: &str) {
+        self.inner.download_finished(url);
+    }
+
+    fn cover_downloaded(&self, target: &Path, url: &CoverUrl) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file_mut(target) {
+            file.cover_url = Some(url.clone());
+        } else if let Some(dir) = state.dirs.get_mut(target) {
+            dir.cover_url = Some(url.clone());
+        }
+        drop(state);
+        self.inner.cover_downloaded(target, url);
+    }
+
+    fn sidecar_saved(&self, path: &Path, sizes: ImageSizes) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(dir) = state.dir_mut(path) {
+            dir.outcome = Outcome::SidecarSaved;
+            dir.sidecar = Some(path.to_path_buf());
+            dir.image_sizes = Some(sizes);
+        }
+        drop(state);
+        self.inner.sidecar_saved(path, sizes);
+    }
+
+    fn tags_updated(&self, path: &Path, tags: &[TagType]) {
+        if let Some(file) = self.state.lock().unwrap().file_mut(path) {
+            file.tags = tags.iter().map(|tag_type| tag_type_name(*tag_type).to_string()).collect();
+        }
+        self.inner.tags_updated(path, tags);
+    }
+
+    fn file_embedded(&self, path: &Path, sizes: ImageSizes) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file_mut(path) {
+            file.outcome = Outcome::Embedded;
+            file.image_sizes = Some(sizes);
+        }
+        drop(state);
+        self.inner.file_embedded(path, sizes);
+    }
+
+    fn art_stripped(&self, path: &Path) {
+        self.state.lock().unwrap().set_outcome(path, Outcome::Stripped);
+        self.inner.art_stripped(path);
+    }
+
+    fn art_unchanged(&self, path: &Path) {
+        self.state.lock().unwrap().set_outcome(path, Outcome::Unchanged);
+        self.inner.art_unchanged(path);
+    }
+
+    fn error(&self, error: &RustyCovError) {
+        let mut state = self.state.lock().unwrap();
+        let message = error.to_string();
+        let attributed = error.path().is_some_and(|path| state.set_outcome(path, Outcome::Error { message: message.clone() }));
+        if !attributed {
+            state.errors.push(message);
+        }
+        drop(state);
+        self.inner.error(error);
+    }
+
+    fn run_finished(&self, summary: &RunSummary) {
+        self.state.lock().unwrap().summary = *summary;
+        self.inner.run_finished(summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::observer::NoopObserver;
+
+    fn io_error(path: &str) -> RustyCovError {
+        RustyCovError::Io { path: PathBuf::from(path), source: io::Error::other("failed") }
+    }
+
+    #[test]
+    fn errors_are_only_attributed_to_items_of_the_run() {
+        let files_by_dir = HashMap::from([(PathBuf::from("/music/album"), vec![PathBuf::from("/music/album/01.flac")])]);
+        let collector = ReportCollector::new(Arc::new(NoopObserver), &files_by_dir, Some("cover"));
+
+        collector.error(&io_error("/music/album/cover.png"));
+        collector.error(&io_error("/music/album/notes.txt"));
+        collector.error(&io_error("/music/other/01.flac"));
+
+        let report = collector.report();
+        assert_eq!(report.directories.len(), 1);
+        assert!(matches!(report.directories[0].outcome, Outcome::Error { .. }));
+        assert_eq!(report.directories[0].files[0].outcome, Outcome::NotProcessed);
+        assert_eq!(report.errors.len(), 2);
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use lofty::file::TaggedFileExt;
+use serde::Serialize;
+
+use crate::helpers::{find_sidecar, image_dimensions};
+use crate::lofty::{front_cover, read_tagged_file};
+
+/// Cover file names looked for when the scan is not given one.
+pub const DEFAULT_SIDECAR_NAMES: [&str; 3] = ["cover", "folder", "front"];
+
+/// Shortest side in pixels below which art is reported as low resolution when the scan is not
+/// given a minimum cover size.
+pub const DEFAULT_LOW_RESOLUTION: u32 = 500;
+
+/// Artwork problem found in a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtIssue {
+    /// No cover file and no track embeds a front cover.
+    MissingArt,
+    /// The cover file or an embedded front cover is smaller than the minimum size.
+    LowResolution,
+    /// The tracks embed different front covers, or only some of them embed one.
+    Inconsistent,
+    /// The directory has a cover file and its tracks also embed pictures.
+    SidecarAndEmbedded,
+}
+
+impl fmt::Display for ArtIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let issue = match self {
+            ArtIssue::MissingArt => "missing art",
+            ArtIssue::LowResolution => "low resolution",
+            ArtIssue::Inconsistent => "inconsistent",
+            ArtIssue::SidecarAndEmbedded => "sidecar and embedded",
+        };
+        f.write_str(issue)
+    }
+}
+
+/// Cover file found next to the tracks of a directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarInfo {
+    pub path: PathBuf,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub bytes: u64,
+}
+
+/// Picture embedded in a file.
+#[derive(Debug, Clone, Serialize)]
+pub struct PictureInfo {
+    /// Picture type as named by lofty, e.g. `CoverFront` or `Leaflet`.
+    pub picture_type: String,
+    pub mime_type: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub bytes: u64,
+}
+
+/// Artwork embedded in a single file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileScan {
+    pub path: PathBuf,
+    /// Pictures across all tags.
+    pub pictures: Vec<PictureInfo>,
+    /// Set when the file's tags could not be read.
+    pub error: Option<String>,
+    /// Hash of the front cover data, used to compare the art of the tracks.
+    #[serde(skip)]
+    front_cover: Option<u64>,
+    #[serde(skip)]
+    front_dimensions: Option<(u32, u32)>,
+}
+
+/// Artwork status of a single directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryScan {
+    pub dir: PathBuf,
+    pub sidecar: Option<SidecarInfo>,
+    /// Whether every readable track embeds the same front cover, or none does.
+    pub consistent: bool,
+    pub issues: Vec<ArtIssue>,
+    pub files: Vec<FileScan>,
+}
+
+/// Artwork status of every directory of the input, produced without writing any file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Scan {
+    pub directories: Vec<DirectoryScan>,
+}
+
+impl Scan {
+    /// Scans `files_by_dir`, as gathered by
+    /// [`RustyCov::populate_from_input`](crate::structs::RustyCov::populate_from_input).
+    ///
+    /// # Arguments
+    ///
+    /// * `files_by_dir` - Files to scan grouped by their parent directory.
+    /// * `sidecar_names` - Cover file names to look for, without extension.
+    /// * `min_size` - Shortest side in pixels below which art is reported as low resolution.
+    pub fn from_files(files_by_dir: &HashMap<PathBuf, Vec<PathBuf>>, sidecar_names: &[&str], min_size: u32) -> Self {
+        let mut directories: Vec<DirectoryScan> =
+            files_by_dir.iter().map(|(dir, files)| scan_dir(dir, files, sidecar_names, min_size)).collect();
+        directories.sort_by(|a, b| a.dir.cmp(&b.dir));
+
+        Scan { directories }
+    }
+
+    /// Renders the scan as CSV with one row per file, repeating the columns of its directory.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "dir,sidecar,sidecar_width,sidecar_height,sidecar_bytes,consistent,issues,file,pictures,picture_types,mime_types,dimensions,picture_bytes,error\n",
+        );
+        for dir in &self.directories {
+            let sidecar = dir.sidecar.as_ref();
+            let issues: Vec<String> = dir.issues.iter().map(ArtIssue::to_string).collect();
+            for file in &dir.files {
+                let join = |values: Vec<String>| values.join(";");
+                let row = [
+                    dir.dir.to_string_lossy().into_owned(),
+                    sidecar.map(|sidecar| sidecar.path.to_string_lossy().into_owned()).unwrap_or_default(),
+                    optional(sidecar.and_then(|sidecar| sidecar.width)),
+                    optional(sidecar.and_then(|sidecar| sidecar.height)),
+                    optional(sidecar.map(|sidecar| sidecar.bytes)),
+                    dir.consistent.to_string(),
+                    issues.join(";"),
+                    file.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                    file.pictures.len().to_string(),
+                    join(file.pictures.iter().map(|picture| picture.picture_type.clone()).collect()),
+                    join(file.pictures.iter().map(|picture| picture.mime_type.clone().unwrap_or_default()).collect()),
+                    join(file.pictures.iter().map(|picture| dimensions(picture.width, picture.height)).collect()),
+                    file.pictures.iter().map(|picture| picture.bytes).sum::<u64>().to_string(),
+                    file.error.clone().unwrap_or_default(),
+                ];
+                let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                csv.push_str(&row.join(","));
+                csv.push('\n');
+            }
+        }
+        csv
+    }
+}
+
+fn scan_dir(dir: &Path, files: &[PathBuf], sidecar_names: &[&str], min_size: u32) -> DirectoryScan {
+    let sidecar = sidecar_names.iter().find_map(|name| find_sidecar(dir, name)).map(|path| {
+        let bytes = std::fs::read(&path).unwrap_or_default();
+        let (width, height) = image_dimensions(&bytes).unzip();
+        SidecarInfo { path, width, height, bytes: bytes.len() as u64 }
+    });
+    let files: Vec<FileScan> = files.iter().map(|path| scan_file(path)).collect();
+
+    let readable: Vec<&FileScan> = files.iter().filter(|file| file.error.is_none()).collect();
+    let mut front_covers: Vec<Option<u64>> = readable.iter().map(|file| file.front_cover).collect();
+    front_covers.dedup();
+    let consistent = front_covers.len() <= 1;
+
+    let is_small = |(width, height): (u32, u32)| width.min(height) < min_size;
+    let small_sidecar = sidecar.as_ref().and_then(|sidecar| sidecar.width.zip(sidecar.height)).is_some_and(is_small);
+    let small_front = files.iter().filter_map(|file| file.front_dimensions).any(is_small);
+    let has_front = readable.iter().any(|file| file.front_cover.is_some());
+    let has_pictures = files.iter().any(|file| !file.pictures.is_empty());
+
+    let issues = [
+        (sidecar.is_none() && !has_front, ArtIssue::MissingArt),
+        (small_sidecar || small_front, ArtIssue::LowResolution),
+        (!consistent, ArtIssue::Inconsistent),
+        (sidecar.is_some() && has_pictures, ArtIssue::SidecarAndEmbedded),
+    ];
+    let issues = issues.into_iter().filter_map(|(found, issue)| found.then_some(issue)).collect();
+
+    DirectoryScan { dir: dir.to_path_buf(), sidecar, consistent, issues, files }
+}
+
+fn scan_file(path: &Path) -> FileScan {
+    let tagged_file = match read_tagged_file(path) {
+        Ok(tagged_file) => tagged_file,
+        Err(e) => {
+            return FileScan {
+                path: path.to_path_buf(),
+                pictures: Vec::new(),
+                error: Some(e.to_string()),
+                front_cover: None,
+                front_dimensions: None,
+            };
+        }
+    };
+
+    let pictures = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|tag| tag.pictures())
+        .map(|picture| {
+            let (width, height) = image_dimensions(picture.data()).unzip();
+            PictureInfo {
+                picture_type: format!("{:?}", picture.pic_type()),
+                mime_type: picture.mime_type().map(|mime_type| mime_type.as_str().to_string()),
+                width,
+                height,
+                bytes: picture.data().len() as u64,
+            }
+        })
+        .collect();
+    let front = front_cover(&tagged_file);
+    let front_cover = front.map(|picture| {
+        let mut hasher = DefaultHasher::new();
+        picture.data().hash(&mut hasher);
+        hasher.finish()
+    });
+    let front_dimensions = front.and_then(|picture| image_dimensions(picture.data()));
+
+    FileScan { path: path.to_path_buf(), pictures, error: None, front_cover, front_dimensions }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn dimensions(width: Option<u32>, height: Option<u32>) -> String {
+    match (width, height) {
+        (Some(width), Some(height)) => format!("{}x{}", width, height),
+        _ => "?".to_string(),
+    }
+}
+
+/// Quotes `field` if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl fmt::Display for Scan {
+    /// Renders the scan as a table per directory.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for dir in &self.directories {
+            match &dir.sidecar {
+                Some(sidecar) => writeln!(
+                    f,
+                    "Folder: {:?} (cover {:?}, {}, {} bytes)",
+                    dir.dir,
+                    sidecar.path.file_name().unwrap_or_default(),
+                    dimensions(sidecar.width, sidecar.height),
+                    sidecar.bytes
+                )?,
+                None => writeln!(f, "Folder: {:?} (no cover file)", dir.dir)?,
+            }
+            if dir.issues.is_empty() {
+                writeln!(f, "  Art: ok")?;
+            } else {
+                let issues: Vec<String> = dir.issues.iter().map(ArtIssue::to_string).collect();
+                writeln!(f, "  Art: {}", issues.join(", "))?;
+            }
+            writeln!(f, "  {:>8} {:<24} {:<24} {:<24} {:>12}  FILE", "PICTURES", "TYPES", "MIME", "SIZES", "BYTES")?;
+            for file in &dir.files {
+                let name = file.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                let types: Vec<&str> = file.pictures.iter().map(|picture| picture.picture_type.as_str()).collect();
+                let mime_types: Vec<&str> = file.pictures.iter().map(|picture| picture.mime_type.as_deref().unwrap_or("?")).collect();
+                let sizes: Vec<String> = file.pictures.iter().map(|picture| dimensions(picture.width, picture.height)).collect();
+                let bytes: u64 = file.pictures.iter().map(|picture| picture.bytes).sum();
+                write!(
+                    f,
+                    "  {:>8} {:<24} {:<24} {:<24} {:>12}  {}",
+                    file.pictures.len(),
+                    types.join(","),
+                    mime_types.join(","),
+                    sizes.join(","),
+                    bytes,
+                    name
+                )?;
+                match &file.error {
+                    Some(error) => writeln!(f, " ({})", error)?,
+                    None => writeln!(f)?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        let count = |issue| self.directories.iter().filter(|dir| dir.issues.contains(&issue)).count();
+        write!(
+            f,
+            "Total: {} folder(s), {} missing art, {} low resolution, {} inconsistent, {} with a cover file and embedded art.",
+            self.directories.len(),
+            count(ArtIssue::MissingArt),
+            count(ArtIssue::LowResolution),
+            count(ArtIssue::Inconsistent),
+            count(ArtIssue::SidecarAndEmbedded)
+        )
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::covit::{CovitConfig, run_covit};
+use crate::error::RustyCovError;
+use crate::lofty::{AlbumTags, read_album_tags};
+use crate::observer::RunObserver;
+use crate::query::QueryConfig;
+use crate::structs::Picked;
+
+/// What a [`CoverSource`] is asked to find a cover for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverQuery {
+    /// Audio file the cover is for.
+    pub path: PathBuf,
+    /// Album artist read from the file's tags.
+    pub album_artist: Option<String>,
+    /// Artist read from the file's tags.
+    pub artist: Option<String>,
+    /// Album read from the file's tags.
+    pub album: Option<String>,
+    /// Release year read from the file's tags.
+    pub year: Option<u32>,
+    /// MusicBrainz release ID (`MUSICBRAINZ_ALBUMID`) read from the file's tags.
+    pub musicbrainz_release_id: Option<String>,
+    /// File name without its extension.
+    pub file_name: Option<String>,
+}
+
+impl CoverQuery {
+    /// Builds the query for `path` with the given tag values, e.g. the most common values of an
+    /// album folder.
+    pub fn new(path: &Path, tags: AlbumTags) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            album_artist: tags.album_artist,
+            artist: tags.artist,
+            album: tags.album,
+            year: tags.year,
+            musicbrainz_release_id: tags.musicbrainz_release_id,
+            file_name: path.file_stem().and_then(|s| s.to_str()).map(str::to_string),
+        }
+    }
+
+    /// Builds the query for `path`, reading its own tags. Files whose tags cannot be read are
+    /// still queried by path and file name.
+    pub fn for_file(path: &Path) -> Self {
+        Self::new(path, read_album_tags(path).unwrap_or_default())
+    }
+}
+
+/// Answer of a [`CoverSource`] to a [`CoverQuery`].
+#[derive(Debug, Clone)]
+pub enum CoverLookup {
+    /// A single cover was chosen, e.g. by the user in covit.
+    Picked(Box<Picked>),
+    /// Covers matching the query, best first. The run uses the first one, or the best scoring one
+    /// in [automatic mode](crate::rank::RankingRules).
+    Candidates(Vec<Picked>),
+    /// No cover was found or the user dismissed the picker.
+    NotFound,
+}
+
+impl CoverLookup {
+    /// Returns the chosen cover, or the first candidate.
+    pub fn into_picked(self) -> Option<Picked> {
+        match self {
+            CoverLookup::Picked(picked) => Some(*picked),
+            CoverLookup::Candidates(candidates) => candidates.into_iter().next(),
+            CoverLookup::NotFound => None,
+        }
+    }
+
+    /// Returns every cover found, a single pick counting as one candidate.
+    pub fn into_candidates(self) -> Vec<Picked> {
+        match self {
+            CoverLookup::Picked(picked) => vec![*picked],
+            CoverLookup::Candidates(candidates) => candidates,
+            CoverLookup::NotFound => Vec::new(),
+        }
+    }
+}
+
+/// Finds cover art for audio files.
+///
+/// [`run_with`](crate::run_with) asks the source configured with
+/// [`RunOptionsBuilder::cover_source`](crate::options::RunOptionsBuilder::cover_source) for every
+/// file, or for the files of each album folder in turn until one yields a cover. Without one, it
+/// downloads covit and uses a [`CovitSource`].
+pub trait CoverSource: Send + Sync {
+    /// Looks up covers for `query`.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - File to find a cover for, along with its tags.
+    /// * `observer` - Receives events such as [`RunObserver::user_closed_tab`].
+    fn find_cover(&self, query: &CoverQuery, observer: &dyn RunObserver) -> Result<CoverLookup, RustyCovError>;
+
+    /// Returns whether the user picks the covers, as in covit. Interactive sources cannot be used
+    /// with [`RunOptions::auto_pick`](crate::options::RunOptions::auto_pick), which would defer
+    /// the user's pick when it scores below the threshold.
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Cover source asking the user to pick a cover in the covit browser UI.
+#[derive(Debug, Clone)]
+pub struct CovitSource {
+    config: CovitConfig,
+    query: QueryConfig,
+}
+
+impl CovitSource {
+    /// Creates a source running the covit binary at `covit_path`, opening the COV website at
+    /// `address` and searching the sources and country `query` resolves for each file.
+    pub fn new<S: Into<String>, A: Into<String>>(covit_path: S, address: A, query: QueryConfig) -> Self {
+        Self { config: CovitConfig::new(covit_path, address), query }
+    }
+
+    /// Gives up on a file when no cover is picked within `pick_timeout`. Waits forever by default.
+    pub fn pick_timeout(mut self, pick_timeout: Option<Duration>) -> Self {
+        self.config.pick_timeout = pick_timeout;
+        self
+    }
+
+    /// Kills the running covit when `token` is tripped.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.config.cancellation = token;
+        self
+    }
+}
+
+impl CoverSource for CovitSource {
+    fn find_cover(&self, query: &CoverQuery, observer: &dyn RunObserver) -> Result<CoverLookup, RustyCovError> {
+        let settings = self.query.settings_for(&query.path);
+        let picked = run_covit(&self.config, query, &settings, observer)?;
+        Ok(picked.map_or(CoverLookup::NotFound, |picked| CoverLookup::Picked(Box::new(picked))))
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
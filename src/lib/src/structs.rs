@@ -1,11 +1,17 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
 use walkdir::WalkDir;
 
 use crate::deps_download::DependencyPaths;
+use crate::error::RustyCovError;
 use crate::helpers::extract_first_number;
+use crate::options::DEFAULT_COV_ADDRESS;
 
 /// Supported audio/video file extensions.
 #[derive(Debug, PartialEq, Eq)]
@@ -69,14 +75,16 @@ pub struct RustyCov<'a> {
 
 impl<'a> Default for RustyCov<'a> {
     fn default() -> Self {
-        Self { files: None, deps: None, cov_address: Some("https://covers.musichoarders.xyz") }
+        Self { files: None, deps: None, cov_address: Some(DEFAULT_COV_ADDRESS) }
     }
 }
 
 impl<'a> RustyCov<'a> {
     /// Populate `files` from a path that may be a file or a directory.
     /// Only entries whose extension maps to a known `FileFormat` are kept.
-    pub fn populate_from_input<S: Into<String>>(&mut self, input: S) {
+    ///
+    /// Returns [`RustyCovError::InputNotFound`] if the path is neither a file nor a directory.
+    pub fn populate_from_input<S: Into<String>>(&mut self, input: S) -> Result<(), RustyCovError> {
         let path_str = input.into();
         let path = PathBuf::from(&path_str);
 
@@ -95,9 +103,8 @@ impl<'a> RustyCov<'a> {
             // Single file case – keep it only if it matches a known format.
             add_file_to_map(&mut files_by_dir, &path);
         } else {
-            eprintln!("❌ Path '{}' does not exist.", path_str);
             self.files = None;
-            return;
+            return Err(RustyCovError::InputNotFound { path });
         }
 
         // Sort files in each directory according numeric ordering rule
@@ -130,6 +137,7 @@ impl<'a> RustyCov<'a> {
         if !files_by_dir.is_empty() {
             self.files = Some(files_by_dir);
         }
+        Ok(())
     }
 }
 
@@ -148,28 +156,180 @@ fn add_file_to_map(files_by_dir: &mut HashMap<PathBuf, Vec<PathBuf>>, file_path:
     }
 }
 
-#[derive(Debug, Deserialize, Default)]
+/// Cover picked in covit, as printed on its `Picked:` line.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Picked {
+    /// Name of the store the cover comes from, e.g. `applemusic`.
+    #[serde(default, alias = "sourceName")]
+    pub source: Option<String>,
     pub big_cover_url: String,
+    #[serde(default)]
+    pub small_cover_url: Option<String>,
+    /// Cover URL on the store itself, which may be larger than the big cover.
+    #[serde(default, alias = "sourceCoverUrl")]
+    pub original_cover_url: Option<String>,
+    #[serde(default)]
     pub release_info: ReleaseInfo,
+    #[serde(default)]
     pub cover_info: CoverInfo,
+    /// Fields not known to this version, kept so they survive the journal and reports.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Default)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
 pub struct ReleaseInfo {
-    pub title: String,
-    pub artist: String,
-    pub date: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub date: Option<String>,
     pub tracks: Option<u32>,
+    /// Page of the release on the store.
+    pub url: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Deserialize, Default)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
 pub struct CoverInfo {
-    pub format: String,
-    pub height: u32,
-    pub width: u32,
-    pub size: u64,
+    /// Image format reported by the store, e.g. `jpg` or `png`.
+    pub format: Option<String>,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+    pub size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Errors returned by [`Picked::parse`].
+#[derive(Error, Debug)]
+pub enum PickError {
+    #[error("Invalid pick JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Pick has an empty bigCoverUrl")]
+    EmptyBigCoverUrl,
+}
+
+/// Which of the cover URLs of a [`Picked`] an image was downloaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverUrlKind {
+    Big,
+    Original,
+    Small,
+}
+
+impl CoverUrlKind {
+    /// Order in which cover URLs are tried when none is configured.
+    pub const DEFAULT_ORDER: [CoverUrlKind; 3] = [CoverUrlKind::Big, CoverUrlKind::Original, CoverUrlKind::Small];
+}
+
+impl fmt::Display for CoverUrlKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CoverUrlKind::Big => "big",
+            CoverUrlKind::Original => "original",
+            CoverUrlKind::Small => "small",
+        })
+    }
+}
+
+impl FromStr for CoverUrlKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "big" => Ok(CoverUrlKind::Big),
+            "original" => Ok(CoverUrlKind::Original),
+            "small" => Ok(CoverUrlKind::Small),
+            other => Err(format!("Unknown cover URL {:?}, expected big, original or small", other)),
+        }
+    }
+}
+
+/// A cover URL along with which one of the pick it is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverUrl {
+    pub kind: CoverUrlKind,
+    pub url: String,
+}
+
+impl Picked {
+    /// Returns the non-empty cover URLs of the pick in the given order of preference.
+    pub fn cover_urls(&self, order: &[CoverUrlKind]) -> Vec<CoverUrl> {
+        let mut urls: Vec<CoverUrl> = Vec::new();
+        for kind in order {
+            let url = match kind {
+                CoverUrlKind::Big => Some(&self.big_cover_url),
+                CoverUrlKind::Original => self.original_cover_url.as_ref(),
+                CoverUrlKind::Small => self.small_cover_url.as_ref(),
+            };
+            if let Some(url) = url.filter(|url| !url.trim().is_empty()) &&
+                !urls.iter().any(|known| known.url == *url)
+            {
+                urls.push(CoverUrl { kind: *kind, url: url.clone() });
+            }
+        }
+        urls
+    }
+
+    /// Parses the JSON payload of a covit `Picked:` line.
+    pub fn parse(json: &str) -> Result<Self, PickError> {
+        let picked: Picked = serde_json::from_str(json)?;
+        if picked.big_cover_url.trim().is_empty() {
+            return Err(PickError::EmptyBigCoverUrl);
+        }
+        Ok(picked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_pick_without_optional_fields() {
+        let picked = Picked::parse(r#"{"bigCoverUrl":"https://example.com/big.jpg"}"#).unwrap();
+        assert_eq!(picked.big_cover_url, "https://example.com/big.jpg");
+        assert_eq!((picked.source, picked.small_cover_url, picked.original_cover_url), (None, None, None));
+        assert_eq!((picked.release_info.title, picked.release_info.tracks), (None, None));
+        assert_eq!((picked.cover_info.width, picked.cover_info.height), (None, None));
+        assert!(picked.extra.is_empty());
+
+        let picked = Picked::parse(r#"{"bigCoverUrl":"big.jpg","sourceName":"deezer","releaseInfo":{},"coverInfo":{"width":600}}"#).unwrap();
+        assert_eq!(picked.source.as_deref(), Some("deezer"));
+        assert_eq!((picked.cover_info.width, picked.cover_info.height), (Some(600), None));
+    }
+
+    #[test]
+    fn rejects_a_pick_without_big_cover_url() {
+        for json in [r#"{"bigCoverUrl":""}"#, r#"{"bigCoverUrl":"  ","smallCoverUrl":"small.jpg"}"#] {
+            assert!(matches!(Picked::parse(json), Err(PickError::EmptyBigCoverUrl)), "{}", json);
+        }
+        assert!(matches!(Picked::parse(r#"{"smallCoverUrl":"small.jpg"}"#), Err(PickError::Json(_))));
+    }
+
+    #[test]
+    fn keeps_unknown_pick_fields() {
+        let json = r#"{"bigCoverUrl":"big.jpg","score":0.9,"releaseInfo":{"title":"Album","label":"Label"},"coverInfo":{"width":1,"dpi":72}}"#;
+        let picked = Picked::parse(json).unwrap();
+        assert_eq!(picked.extra.get("score"), Some(&Value::from(0.9)));
+        assert_eq!(picked.release_info.extra.get("label"), Some(&Value::from("Label")));
+        assert_eq!(picked.cover_info.extra.get("dpi"), Some(&Value::from(72)));
+
+        // The unknown fields survive a round trip through the journal and reports
+        let round_trip = serde_json::to_value(&picked).unwrap();
+        assert_eq!(round_trip["score"], 0.9);
+        assert_eq!(round_trip["releaseInfo"]["label"], "Label");
+    }
+
+    #[test]
+    fn rejects_malformed_cover_dimensions() {
+        for dimension in [r#""1200px""#, "-1", "1.5", "4294967296"] {
+            let json = format!(r#"{{"bigCoverUrl":"big.jpg","coverInfo":{{"width":{}}}}}"#, dimension);
+            assert!(matches!(Picked::parse(&json), Err(PickError::Json(_))), "{}", json);
+        }
+    }
 }